@@ -0,0 +1,247 @@
+use crate::engine::{self, MATE};
+use crate::Game;
+
+// centipawns a move loses against the best one to be an inaccuracy, a
+// mistake or a blunder
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+// scores count as at most this much, so one missed mate doesn't swamp the
+// average loss
+const SCORE_CAP: i32 = 1000;
+
+// How a move compares to the engine's best
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Judgement {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn of(loss: i32) -> Judgement {
+        match loss {
+            0 => Judgement::Best,
+            _ if loss >= BLUNDER => Judgement::Blunder,
+            _ if loss >= MISTAKE => Judgement::Mistake,
+            _ if loss >= INACCURACY => Judgement::Inaccuracy,
+            _ => Judgement::Good,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Judgement::Best => "Best",
+            Judgement::Good => "Good",
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+
+    // The NAG it gets in PGN: $6 "?!", $2 "?" and $4 "??"
+    pub fn nag(self) -> Option<u8> {
+        match self {
+            Judgement::Inaccuracy => Some(6),
+            Judgement::Mistake => Some(2),
+            Judgement::Blunder => Some(4),
+            _ => None,
+        }
+    }
+}
+
+// A move of the main line with the engine's view of it. Scores are in
+// centipawns from white's side, mates score as in the engine.
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis {
+    // the game tree node the move leads to
+    pub node: usize,
+    pub san: String,
+    // whether white played it
+    pub white: bool,
+    pub before: i32,
+    pub after: i32,
+    // the engine's move in SAN
+    pub best: String,
+    // centipawns lost against the best move, never below 0
+    pub loss: i32,
+    // 0 to 100, from the drop in winning chances
+    pub accuracy: f64,
+    pub judgement: Judgement,
+}
+
+// How one side played
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub moves: usize,
+    // average centipawn loss
+    pub acpl: f64,
+    pub accuracy: f64,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub depth: u32,
+    pub moves: Vec<MoveAnalysis>,
+    pub white: Summary,
+    pub black: Summary,
+}
+
+// Searches every position of the main line `depth` moves deep and judges
+// each move by how much worse the position got for the side that played it
+pub fn analyse(game: &Game, depth: u32) -> Report {
+    let nodes: Vec<usize> = std::iter::once(0).chain(game.main_line(0)).collect();
+    // the best move and the score for white of every position
+    let positions: Vec<(Option<String>, i32)> = nodes
+        .iter()
+        .map(|&node| {
+            let board = game.board_at(node);
+            let (best, score) = match engine::search(board, depth) {
+                Some(((from, to, promotion), score)) => {
+                    (Some(board.san(from, to, promotion)), score)
+                }
+                None => (None, engine::score(board, 0)),
+            };
+            (best, if board.turn { score } else { -score })
+        })
+        .collect();
+
+    let mut moves = vec![];
+    for (i, &node) in nodes.iter().enumerate().skip(1) {
+        let white = game.board_at(nodes[i - 1]).turn;
+        let san = game.move_at(node).unwrap().san.clone();
+        let (best, before) = positions[i - 1].clone();
+        let after = positions[i].1;
+        let best = best.unwrap_or_default();
+        // scores for the side that moved, capped
+        let side = |score: i32| {
+            let score = score.clamp(-SCORE_CAP, SCORE_CAP);
+            if white {
+                score
+            } else {
+                -score
+            }
+        };
+        // only the engine's move is best, any other loses at least a little even
+        // when the search after it finds more than the one before it did
+        let loss = if san == best {
+            0
+        } else {
+            (side(before) - side(after)).max(1)
+        };
+        let drop = winning_chances(side(before)) - winning_chances(side(after));
+        moves.push(MoveAnalysis {
+            node,
+            san,
+            white,
+            before,
+            after,
+            best,
+            loss,
+            accuracy: (103.1668 * (-0.04354 * drop.max(0.0)).exp() - 3.1669).clamp(0.0, 100.0),
+            judgement: Judgement::of(loss),
+        });
+    }
+    Report {
+        depth,
+        white: summary(moves.iter().filter(|mv| mv.white)),
+        black: summary(moves.iter().filter(|mv| !mv.white)),
+        moves,
+    }
+}
+
+// From 0 to 100, how likely a side is to win with this score, with the
+// curve Lichess fitted to its games
+fn winning_chances(score: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * score as f64).exp()) - 1.0)
+}
+
+fn summary<'a>(moves: impl Iterator<Item = &'a MoveAnalysis>) -> Summary {
+    let mut summary = Summary::default();
+    let (mut loss, mut accuracy) = (0, 0.0);
+    for mv in moves {
+        summary.moves += 1;
+        loss += mv.loss;
+        accuracy += mv.accuracy;
+        match mv.judgement {
+            Judgement::Inaccuracy => summary.inaccuracies += 1,
+            Judgement::Mistake => summary.mistakes += 1,
+            Judgement::Blunder => summary.blunders += 1,
+            _ => {}
+        }
+    }
+    if summary.moves > 0 {
+        summary.acpl = loss as f64 / summary.moves as f64;
+        summary.accuracy = accuracy / summary.moves as f64;
+    }
+    summary
+}
+
+// A score for PGN and the report: pawns like "0.35" and "-1.20", mates
+// like "#3", "#-2" when black mates
+pub fn format_score(score: i32) -> String {
+    if score.abs() > MATE / 2 {
+        let moves = (MATE - score.abs() + 1) / 2;
+        format!("#{}{}", if score < 0 { "-" } else { "" }, moves)
+    } else {
+        format!("{:.2}", score as f64 / 100.0)
+    }
+}
+
+// The game with the analysis in it: NAGs on the inaccuracies, mistakes and
+// blunders, and a comment on every move with the score after it as
+// [%eval ...] and what the engine would have played instead. The notes of
+// an earlier analysis are replaced, other comments stay.
+pub fn annotate(game: &Game, report: &Report) -> Game {
+    let mut game = game.clone();
+    game.set_tag("Annotator", &format!("Engine, depth {}", report.depth));
+    for mv in &report.moves {
+        if let Some(nag) = mv.judgement.nag() {
+            game.add_nag(mv.node, nag);
+        }
+        let mut comment = without_analysis(game.comment(mv.node));
+        if !game.board_at(mv.node).is_finished() {
+            comment = join(&comment, &format!("[%eval {}]", format_score(mv.after)));
+        }
+        if mv.judgement.nag().is_some() {
+            let note = format!("{}. {} was best.", mv.judgement.name(), mv.best);
+            comment = join(&comment, &note);
+        }
+        game.set_comment(mv.node, &comment);
+    }
+    game
+}
+
+// A comment without the [%eval ...] and "Mistake. Nf3 was best." notes
+// annotate puts in
+fn without_analysis(comment: &str) -> String {
+    let mut comment = comment.to_string();
+    let notes = [
+        ("[%eval ", "]"),
+        ("Inaccuracy. ", " was best."),
+        ("Mistake. ", " was best."),
+        ("Blunder. ", " was best."),
+    ];
+    for (start, end) in notes {
+        while let Some(i) = comment.find(start) {
+            let Some(length) = comment[i..].find(end) else {
+                break;
+            };
+            comment.replace_range(i..i + length + end.len(), "");
+        }
+    }
+    comment.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn join(text: &str, more: &str) -> String {
+    if text.is_empty() {
+        more.to_string()
+    } else {
+        format!("{} {}", text, more)
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use crate::crazyhouse::hand_letter;
+use crate::{create_variant_board, Board, GameState, Variant};
+
+// Two boards played at the same time by two teams of two. Team 0 is white on
+// board 0 and black on board 1, team 1 the other two. A captured piece goes
+// to the pocket of the capturer's partner, who can drop it on their board.
+#[derive(Clone, Debug)]
+pub struct Bughouse {
+    pub boards: [Board; 2],
+    // time left on each board for white and black
+    pub clocks: [[Duration; 2]; 2],
+    // the moves of each board in SAN
+    pub moves: [Vec<String>; 2],
+    // board and color of the first player to run out of time
+    flagged: Option<(usize, bool)>,
+}
+
+// How a match ended: the winning team, None for a draw, and why
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub winner: Option<usize>,
+    pub reason: String,
+}
+
+impl Bughouse {
+    // Both boards in the start position with `time` on every clock
+    pub fn new(time: Duration) -> Bughouse {
+        let board = create_variant_board(Variant::Bughouse, None).unwrap();
+        Bughouse {
+            boards: [board.clone(), board],
+            clocks: [[time; 2]; 2],
+            moves: [vec![], vec![]],
+            flagged: None,
+        }
+    }
+
+    // The team of the player with `color` on `board`
+    pub fn team(board: usize, color: bool) -> usize {
+        if (board == 0) == color {
+            0
+        } else {
+            1
+        }
+    }
+
+    // Plays a move on one board, see Board::is_legal for drops. What it
+    // captures goes to the partner on the other board.
+    pub fn play(&mut self, board: usize, from: usize, to: usize, promotion: Option<char>) -> bool {
+        let current = &self.boards[board];
+        if self.result().is_some() || !current.is_legal(from, to, promotion) {
+            return false;
+        }
+        let captured = current.captured_piece(from, to);
+        let san = current.san(from, to, promotion);
+        self.boards[board].make_move(from, to, promotion);
+        self.moves[board].push(san);
+        if let Some(piece) = captured {
+            // the partner plays the color of the captured piece on the other board
+            let partner = &mut self.boards[1 - board];
+            partner.pocket.push(hand_letter(&piece, piece.color()));
+            // a new piece in hand can be the way out of a mate
+            partner.update_state();
+        }
+        true
+    }
+
+    // Runs the clocks of the sides to move on both boards
+    pub fn tick(&mut self, elapsed: Duration) {
+        if self.result().is_some() {
+            return;
+        }
+        for board in 0..2 {
+            let color = self.boards[board].turn;
+            let clock = &mut self.clocks[board][clock_index(color)];
+            *clock = clock.saturating_sub(elapsed);
+            if clock.is_zero() && self.flagged.is_none() {
+                self.flagged = Some((board, color));
+            }
+        }
+    }
+
+    pub fn clock(&self, board: usize, color: bool) -> Duration {
+        self.clocks[board][clock_index(color)]
+    }
+
+    // The match ends as soon as one board is decided, or a clock runs out
+    pub fn result(&self) -> Option<Outcome> {
+        let side = |color: bool| if color { "White" } else { "Black" };
+        if let Some((board, color)) = self.flagged {
+            return Some(Outcome {
+                winner: Some(1 - Bughouse::team(board, color)),
+                reason: format!("{} ran out of time on board {}", side(color), board + 1),
+            });
+        }
+        for (i, board) in self.boards.iter().enumerate() {
+            match board.game_state {
+                GameState::Checkmate => {
+                    return Some(Outcome {
+                        winner: Some(1 - Bughouse::team(i, board.turn)),
+                        reason: format!("{} is mated on board {}", side(board.turn), i + 1),
+                    })
+                }
+                GameState::Stalemate => {
+                    return Some(Outcome {
+                        winner: None,
+                        reason: format!("Stalemate on board {}", i + 1),
+                    })
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+fn clock_index(color: bool) -> usize {
+    if color {
+        0
+    } else {
+        1
+    }
+}
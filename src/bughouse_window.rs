@@ -0,0 +1,191 @@
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use glutin_window::GlutinWindow;
+use opengl_graphics::GlGraphics;
+use piston::event_loop::Events;
+use piston::input::{Button, Key, MouseButton};
+use piston::{MouseCursorEvent, PressEvent, ReleaseEvent, RenderEvent, ResizeEvent, UpdateEvent};
+
+use chess::engine::{self, Move};
+use chess::{Board, Bughouse};
+
+use crate::render::{self, View};
+use crate::theme::Skin;
+
+// how far ahead the bots look, more gets slow with all the drops
+const BOT_DEPTH: u32 = 2;
+// bots take at least this long so their moves can be followed
+const BOT_DELAY: Duration = Duration::from_millis(800);
+
+// Plays a bughouse match in the window until it's closed. `bots` says which
+// seats the computer plays, by board and then white and black. F flips both
+// boards and N starts a new match.
+pub fn run(
+    window: &mut GlutinWindow,
+    events: &mut Events,
+    gl: &mut GlGraphics,
+    skin: &mut Skin,
+    mut view: View,
+    bots: [[bool; 2]; 2],
+    time: Duration,
+) {
+    let mut bughouse = Bughouse::new(time);
+    // the board a piece is being dragged on, or whose promotion picker is open
+    let mut active: Option<usize> = None;
+    // the bots think in the background so the clocks keep running
+    let mut thinking: [Option<Receiver<Option<Move>>>; 2] = [None, None];
+
+    while let Some(e) = events.next(window) {
+        if let Some(args) = e.resize_args() {
+            view.layout = render::Layout::new(args.window_size);
+        }
+
+        if let Some(position) = e.mouse_cursor_args() {
+            view.mouse = position;
+        }
+
+        // any key takes a waiting promotion back
+        if let Some(Button::Keyboard(_)) = e.press_args() {
+            if view.promoting.take().is_some() {
+                active = None;
+            }
+        }
+        match e.press_args() {
+            Some(Button::Keyboard(Key::F)) => view.flipped = !view.flipped,
+            Some(Button::Keyboard(Key::N)) => {
+                bughouse = Bughouse::new(time);
+                thinking = [None, None];
+                active = None;
+                view.dragging = None;
+                view.dropping = None;
+            }
+            _ => {}
+        }
+
+        if let Some(args) = e.update_args() {
+            bughouse.tick(Duration::from_secs_f64(args.dt));
+            for (i, search) in thinking.iter_mut().enumerate() {
+                if bughouse.result().is_some() || !is_bot(&bots, &bughouse.boards[i], i) {
+                    continue;
+                }
+                match search {
+                    None => *search = Some(start_search(bughouse.boards[i].clone())),
+                    Some(receiver) => {
+                        if let Ok(mv) = receiver.try_recv() {
+                            *search = None;
+                            // a move that is no longer legal is searched again
+                            if let Some((from, to, promotion)) = mv {
+                                bughouse.play(i, from, to, promotion);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // the same dragging and clicking as on the single board, on whichever
+        // board has a human to move
+        if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+            let human =
+                |i: usize| bughouse.result().is_none() && !is_bot(&bots, &bughouse.boards[i], i);
+            if let (Some((from, to)), Some(i)) = (view.promoting, active) {
+                // a piece of the picker promotes to it, anywhere else takes the move back
+                let sub = render::bughouse_view(&view, i, true);
+                if let Some(name) = render::promotion_at(view.mouse, &bughouse.boards[i], &sub) {
+                    bughouse.play(i, from, to, Some(name));
+                }
+                view.promoting = None;
+                active = None;
+            } else if let Some((i, pos)) = render::bughouse_square_at(view.mouse, &bughouse, &view)
+            {
+                let board = &bughouse.boards[i];
+                let target = board
+                    .selected
+                    .filter(|&sq| board.get_valid_moves(sq).contains(&pos));
+                let own_piece = board.board[pos]
+                    .as_ref()
+                    .is_some_and(|piece| piece.color() == board.turn);
+                if let (Some(from), true) = (target, human(i)) {
+                    play(&mut bughouse, &mut view, &mut active, (i, from, pos));
+                } else if own_piece && human(i) {
+                    bughouse.boards[i].selected = Some(pos);
+                    view.dragging = Some(pos);
+                    active = Some(i);
+                } else {
+                    bughouse.boards[i].selected = None;
+                }
+            } else if let Some((i, name)) = render::bughouse_pocket_at(view.mouse, &bughouse, &view)
+            {
+                if human(i) && name.is_ascii_uppercase() == bughouse.boards[i].turn {
+                    bughouse.boards[i].selected = None;
+                    view.dropping = Some(name);
+                    active = Some(i);
+                }
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+            let under = render::bughouse_square_at(view.mouse, &bughouse, &view);
+            // an open picker keeps its board active until a piece is picked
+            if let (Some(i), None) = (active, view.promoting) {
+                active = None;
+                // pieces only land on the board they came from
+                let to = under.filter(|&(j, _)| j == i).map(|(_, to)| to);
+                if let (Some(from), Some(to)) = (view.dragging.take(), to) {
+                    if to != from && bughouse.boards[i].get_valid_moves(from).contains(&to) {
+                        play(&mut bughouse, &mut view, &mut active, (i, from, to));
+                    }
+                }
+                if let (Some(name), Some(to)) = (view.dropping.take(), to) {
+                    bughouse.play(i, to, to, Some(name));
+                }
+                view.dragging = None;
+                view.dropping = None;
+            }
+        }
+
+        if let Some(args) = e.render_args() {
+            view.layout = render::Layout::new(args.window_size);
+            gl.draw(args.viewport(), |c, g| {
+                graphics::clear(skin.theme.background.0, g);
+                render::draw_bughouse(&c, g, &bughouse, skin, &view, active);
+            });
+        }
+    }
+}
+
+// Whether the computer plays the side to move on board `i`
+fn is_bot(bots: &[[bool; 2]; 2], board: &Board, i: usize) -> bool {
+    bots[i][if board.turn { 0 } else { 1 }]
+}
+
+// A board move by a human, as (board, from, to). A pawn reaching the last
+// rank waits for the promotion picker, which stays on that board as `active`.
+fn play(
+    bughouse: &mut Bughouse,
+    view: &mut View,
+    active: &mut Option<usize>,
+    (i, from, to): (usize, usize, usize),
+) {
+    bughouse.boards[i].selected = None;
+    if bughouse.boards[i].is_promotion(from, to) {
+        view.promoting = Some((from, to));
+        *active = Some(i);
+    } else {
+        bughouse.play(i, from, to, None);
+    }
+}
+
+fn start_search(board: Board) -> Receiver<Option<Move>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let started = Instant::now();
+        let mv = engine::best_move(&board, BOT_DEPTH);
+        thread::sleep(BOT_DELAY.saturating_sub(started.elapsed()));
+        // nobody listens any more after a new match was started
+        let _ = sender.send(mv);
+    });
+    receiver
+}
@@ -0,0 +1,64 @@
+use rand::Rng;
+
+// Knight placements among the five squares left after the bishops and queen,
+// in the order of the standard numbering
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+// The FEN of Chess960 start position `index`, numbered 0 to 959 as usual
+// (Scharnagl), so 518 is the ordinary start position
+pub fn chess960_fen(index: usize) -> Result<String, String> {
+    if index >= 960 {
+        return Err(format!(
+            "No Chess960 position {}, they go from 0 to 959",
+            index
+        ));
+    }
+    let mut rank = [' '; 8];
+    let mut n = index;
+    // bishops on opposite colors: b, d, f or h for one, a, c, e or g for the other
+    rank[n % 4 * 2 + 1] = 'b';
+    n /= 4;
+    rank[n % 4 * 2] = 'b';
+    n /= 4;
+    place(&mut rank, n % 6, 'q');
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    // the second knight is counted before the first one is placed
+    place(&mut rank, second, 'n');
+    place(&mut rank, first, 'n');
+    // the king ends up between the rooks
+    for name in ['r', 'k', 'r'] {
+        place(&mut rank, 0, name);
+    }
+
+    let black: String = rank.iter().collect();
+    let white = black.to_ascii_uppercase();
+    Ok(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+        black, white
+    ))
+}
+
+// One of the 960 start positions at random, with its number
+pub fn random_chess960() -> (usize, String) {
+    let index = rand::thread_rng().gen_range(0..960);
+    (index, chess960_fen(index).unwrap())
+}
+
+// Puts a piece on the `nth` empty square of the rank
+fn place(rank: &mut [char; 8], nth: usize, name: char) {
+    if let Some(square) = rank.iter_mut().filter(|c| **c == ' ').nth(nth) {
+        *square = name;
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{Board, Piece};
+
+// The order pieces are listed in a pocket, as in FEN
+pub(crate) const POCKET_ORDER: &str = "QRBNP";
+
+// Crazyhouse: captured pieces go to the capturer's pocket and can be dropped
+// back on the board instead of moving. Drops are written as a move from the
+// target square to itself, with the piece letter where the promotion goes.
+impl Board {
+    // The pieces `color` has in hand and how many of each, queens first
+    pub fn pocket(&self, color: bool) -> Vec<(char, usize)> {
+        POCKET_ORDER
+            .chars()
+            .map(|name| {
+                if color {
+                    name
+                } else {
+                    name.to_ascii_lowercase()
+                }
+            })
+            .map(|name| (name, self.pocket.iter().filter(|&&p| p == name).count()))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    // Whether the side to move can drop the piece `name`, in either case, on `to`
+    pub fn can_drop(&self, name: char, to: usize) -> bool {
+        let name = self.own_letter(name);
+        let pawn = name.eq_ignore_ascii_case(&'p');
+        if !self.variant.has_drops()
+            || to >= self.size()
+            || self.board[to].is_some()
+            || !self.pocket.contains(&name)
+            || (pawn && (self.row(to) == 0 || self.row(to) == self.height - 1))
+        {
+            return false;
+        }
+        let mut after = self.clone();
+        after.apply_drop(name, to);
+        after.allowed_after_move(self.turn)
+    }
+
+    // Every legal drop of the side to move, as the piece letter and square
+    pub fn drops(&self) -> Vec<(char, usize)> {
+        let mut drops = vec![];
+        for (name, _) in self.pocket(self.turn) {
+            for to in 0..self.size() {
+                if self.can_drop(name, to) {
+                    drops.push((name, to));
+                }
+            }
+        }
+        drops
+    }
+
+    // Takes a piece out of the pocket of the side to move and puts it on `to`
+    pub(crate) fn apply_drop(&mut self, name: char, to: usize) {
+        let name = self.own_letter(name);
+        if let Some(i) = self.pocket.iter().position(|&p| p == name) {
+            self.pocket.remove(i);
+        }
+        self.board[to] = Piece::from_char(name);
+        self.en_passant = None;
+        if name.eq_ignore_ascii_case(&'p') {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        self.last_move = Some((to, to));
+    }
+
+    // Puts a captured piece in the pocket of the side that took it, with its
+    // color flipped
+    pub(crate) fn pocket_capture(&mut self, captured: &Piece) {
+        self.pocket.push(hand_letter(captured, !captured.color));
+    }
+
+    // A piece letter in the case of the side to move
+    fn own_letter(&self, name: char) -> char {
+        if self.turn {
+            name.to_ascii_uppercase()
+        } else {
+            name.to_ascii_lowercase()
+        }
+    }
+}
+
+// The letter a captured piece goes into a pocket as, for the side `color`.
+// Promoted pieces go back to being pawns.
+pub(crate) fn hand_letter(captured: &Piece, color: bool) -> char {
+    let name = if captured.promoted {
+        'p'
+    } else {
+        captured.name
+    };
+    if color {
+        name.to_ascii_uppercase()
+    } else {
+        name.to_ascii_lowercase()
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::Board;
+
+// How a board is printed as text
+#[derive(Clone, Debug)]
+pub struct DisplayOptions {
+    // Unicode chess glyphs instead of FEN letters
+    pub unicode: bool,
+    // rank numbers on the left and file letters underneath
+    pub coordinates: bool,
+    // print from black's side, with h1 in the top left corner
+    pub flipped: bool,
+    // squares to mark with brackets, e.g. the last move
+    pub highlights: Vec<usize>,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> DisplayOptions {
+        DisplayOptions {
+            unicode: false,
+            coordinates: true,
+            flipped: false,
+            highlights: vec![],
+        }
+    }
+}
+
+// Borrowed board plus options, returned by `Board::display`
+pub struct BoardDisplay<'a> {
+    board: &'a Board,
+    options: DisplayOptions,
+}
+
+impl Board {
+    pub fn display(&self, options: DisplayOptions) -> BoardDisplay<'_> {
+        BoardDisplay {
+            board: self,
+            options,
+        }
+    }
+}
+
+impl fmt::Display for BoardDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = &self.options;
+        let (width, height) = (self.board.width, self.board.height);
+        // rank numbers take two columns on boards with ten ranks or more
+        let label = height.to_string().len();
+        for row in 0..height {
+            let rank = if options.flipped {
+                height - 1 - row
+            } else {
+                row
+            };
+            if options.coordinates {
+                write!(f, "{:>label$} ", height - rank)?;
+            }
+            for col in 0..width {
+                let file = if options.flipped {
+                    width - 1 - col
+                } else {
+                    col
+                };
+                let pos = file + rank * width;
+                let symbol = match &self.board.board[pos] {
+                    Some(piece) if options.unicode => piece.glyph(),
+                    Some(piece) => piece.name,
+                    None if options.unicode => '\u{00B7}',
+                    None => '.',
+                };
+                if options.highlights.contains(&pos) {
+                    write!(f, "[{}]", symbol)?;
+                } else {
+                    write!(f, " {} ", symbol)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        if options.coordinates {
+            write!(f, "{:label$} ", "")?;
+            for col in 0..width {
+                let file = if options.flipped {
+                    width - 1 - col
+                } else {
+                    col
+                };
+                write!(f, " {} ", (b'a' + file as u8) as char)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// `{}` prints ASCII letters, `{:#}` prints Unicode glyphs
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = DisplayOptions {
+            unicode: f.alternate(),
+            ..DisplayOptions::default()
+        };
+        write!(f, "{}", self.display(options))
+    }
+}
@@ -0,0 +1,84 @@
+use chess::{create_variant_board, Board, Piece};
+
+// The setup mode: a position being edited, with the palette piece that
+// clicks on the board put down. No palette piece means clicks erase.
+pub struct Editor {
+    pub board: Board,
+    pub palette: Option<char>,
+}
+
+// The buttons of the setup panel
+#[derive(Clone, Copy, PartialEq)]
+pub enum EditorItem {
+    Piece(char),
+    Erase,
+    Turn,
+    // the castling right with the rook on that square
+    Castling(usize),
+    EnPassant,
+    // the palette piece into or out of its pocket, in variants with drops
+    AddToPocket,
+    TakeFromPocket,
+    Clear,
+    Reset,
+    Play,
+    Cancel,
+}
+
+impl Editor {
+    pub fn new(board: &Board) -> Editor {
+        let mut board = board.clone();
+        board.selected = None;
+        board.last_move = None;
+        Editor {
+            board,
+            palette: Some('P'),
+        }
+    }
+
+    // Puts the palette piece on a square. Clicking the same piece again removes it.
+    pub fn click_square(&mut self, pos: usize) {
+        let same = match (&self.board.board[pos], self.palette) {
+            (Some(piece), Some(name)) => piece.name == name,
+            _ => false,
+        };
+        let piece = match self.palette {
+            Some(name) if !same => Piece::from_char(name),
+            _ => None,
+        };
+        self.board.place(pos, piece);
+    }
+
+    // Everything but Play and Cancel, which the caller handles
+    pub fn apply(&mut self, item: EditorItem) {
+        match item {
+            EditorItem::Piece(name) => self.palette = Some(name),
+            EditorItem::Erase => self.palette = None,
+            EditorItem::Turn => self.board.set_turn(!self.board.turn),
+            EditorItem::Castling(rook) => {
+                self.board.toggle_castling(rook);
+            }
+            EditorItem::EnPassant => self.board.cycle_en_passant(),
+            EditorItem::AddToPocket => {
+                if let Some(name) = self.palette {
+                    self.board.add_to_pocket(name);
+                }
+            }
+            EditorItem::TakeFromPocket => {
+                if let Some(name) = self.palette {
+                    self.board.take_from_pocket(name);
+                }
+            }
+            // both keep the variant and the size of the board being set up
+            EditorItem::Clear => {
+                let variant = self.board.variant;
+                self.board = Board::empty(self.board.width, self.board.height);
+                self.board.variant = variant;
+            }
+            EditorItem::Reset => {
+                self.board = create_variant_board(self.board.variant, None).unwrap()
+            }
+            EditorItem::Play | EditorItem::Cancel => {}
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::time::{Duration, Instant};
+
+use crate::book::{Book, Choice};
+use crate::retrograde::{self, Distance};
+use crate::{piece_value, Board, GameState, Variant};
+
+// A move as (from, to, promotion), drops included, see Board::all_moves
+pub type Move = (usize, usize, Option<char>);
+
+// Scores are in centipawns for the side to move. Mates score MATE minus the
+// number of moves to get there, so quicker mates score higher.
+pub const MATE: i32 = 100_000;
+
+// captures are searched this many moves past the depth, to see exchanges through
+const QUIESCENCE_DEPTH: u32 = 4;
+
+// The best move for the side to move with its score, searching `depth` moves
+// ahead. None when there is no legal move.
+pub fn search(board: &Board, depth: u32) -> Option<(Move, i32)> {
+    search_root(board, depth, None, None)
+}
+
+// Iterative deepening: searches one move deeper at a time until `max_depth`,
+// a mate is found or `time` runs out. Returns the move and score of the
+// deepest search that finished and its depth. The first depth always
+// finishes, so there's a move whenever there is a legal one.
+pub fn search_timed(board: &Board, max_depth: u32, time: Duration) -> Option<(Move, i32, u32)> {
+    let deadline = Instant::now() + time;
+    let (mut mv, mut score) = search_root(board, 1, None, None)?;
+    let mut depth = 1;
+    while depth < max_depth && score.abs() <= MATE / 2 && Instant::now() < deadline {
+        match search_root(board, depth + 1, Some(mv), Some(deadline)) {
+            Some(result) => (mv, score) = result,
+            None => break,
+        }
+        depth += 1;
+    }
+    Some((mv, score, depth))
+}
+
+// The root of search, trying `first` before the other moves. None when there
+// is no legal move or the deadline passed before the search finished.
+fn search_root(
+    board: &Board,
+    depth: u32,
+    first: Option<Move>,
+    deadline: Option<Instant>,
+) -> Option<(Move, i32)> {
+    let mut moves = ordered_moves(board, false);
+    // the best move of the last depth is likely still the best, and finds
+    // the cutoffs sooner
+    if let Some(i) = first.and_then(|first| moves.iter().position(|&mv| mv == first)) {
+        let mv = moves.remove(i);
+        moves.insert(0, mv);
+    }
+    let mut best: Option<(Move, i32)> = None;
+    let mut alpha = -MATE - 1;
+    for mv in moves {
+        let after = play(board, mv);
+        let score = -negamax(
+            &after,
+            depth.saturating_sub(1),
+            -MATE - 1,
+            -alpha,
+            1,
+            deadline,
+        );
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some((mv, score));
+        }
+    }
+    // the scores are made up once the time is up
+    if timed_out(deadline) {
+        return None;
+    }
+    best
+}
+
+fn timed_out(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+// The score of the position for the side to move, searching `depth` moves
+// ahead. Finished games score as search would see them.
+pub fn score(board: &Board, depth: u32) -> i32 {
+    negamax(board, depth, -MATE - 1, MATE + 1, 0, None)
+}
+
+// Just the move of search
+pub fn best_move(board: &Board, depth: u32) -> Option<Move> {
+    search(board, depth).map(|(mv, _)| mv)
+}
+
+// The move to play: from the opening book while the position is in it,
+// otherwise the best move of a search
+pub fn choose_move(board: &Board, depth: u32, book: Option<&Book>) -> Option<Move> {
+    book.and_then(|book| book.pick(board, Choice::Weighted))
+        .or_else(|| best_move(board, depth))
+}
+
+// When find_blunder calls a move a blunder: it scores at least `material`
+// centipawns below the best move, or it lets the opponent mate, as far as a
+// search `depth` moves deep sees
+#[derive(Clone, Copy, Debug)]
+pub struct BlunderCheck {
+    pub depth: u32,
+    pub material: i32,
+}
+
+impl Default for BlunderCheck {
+    fn default() -> BlunderCheck {
+        BlunderCheck {
+            depth: 2,
+            material: 200,
+        }
+    }
+}
+
+// What's wrong with a blunder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blunder {
+    // the opponent mates in this many moves
+    AllowsMate(u32),
+    // centipawns lost against the best move
+    LosesMaterial(i32),
+}
+
+// Checks a legal move of the side to move against the best one. A move
+// isn't a blunder when every other move is as bad.
+pub fn find_blunder(board: &Board, mv: Move, check: &BlunderCheck) -> Option<Blunder> {
+    let (_, best) = search(board, check.depth)?;
+    let after = play(board, mv);
+    let score = -negamax(
+        &after,
+        check.depth.saturating_sub(1),
+        -MATE - 1,
+        MATE + 1,
+        1,
+        None,
+    );
+    if score >= best {
+        return None;
+    }
+    if score < -MATE / 2 {
+        // plies count from before the move, the opponent's mate in n is 2n of them
+        let plies = (MATE + score) as u32;
+        return Some(Blunder::AllowsMate(plies / 2));
+    }
+    (best - score >= check.material).then_some(Blunder::LosesMaterial(best - score))
+}
+
+// Material and a little for central pieces, for the side to move. Pockets
+// count as material too. In antichess having less is better.
+pub fn evaluate(board: &Board) -> i32 {
+    let mut score = 0;
+    for (pos, piece) in board.board.iter().enumerate() {
+        if let Some(piece) = piece {
+            let (file, row) = (board.file(pos), board.row(pos));
+            // 0 on the edge up to 3 in the center, kings stay out of it
+            let center = file
+                .min(board.width - 1 - file)
+                .min(row)
+                .min(board.height - 1 - row)
+                .min(3) as i32;
+            let center = if piece.name.eq_ignore_ascii_case(&'k') {
+                0
+            } else {
+                center
+            };
+            let value = piece_value(piece.name) * 100 + center * 5;
+            score += if piece.color() == board.turn {
+                value
+            } else {
+                -value
+            };
+        }
+    }
+    for &name in &board.pocket {
+        let value = piece_value(name) * 100;
+        score += if name.is_ascii_uppercase() == board.turn {
+            value
+        } else {
+            -value
+        };
+    }
+    if board.variant == Variant::Antichess {
+        -score
+    } else {
+        score
+    }
+}
+
+// Alpha-beta search. Past the deadline it gives up and returns 0, the caller
+// throws away the scores of a search that ran out of time.
+fn negamax(
+    board: &Board,
+    depth: u32,
+    mut alpha: i32,
+    beta: i32,
+    ply: i32,
+    deadline: Option<Instant>,
+) -> i32 {
+    if timed_out(deadline) {
+        return 0;
+    }
+    if let Some(score) = game_over_score(board, ply) {
+        return score;
+    }
+    if let Some(distance) = retrograde::probe(board) {
+        return table_score(distance, ply);
+    }
+    if depth == 0 {
+        return quiesce(board, alpha, beta, ply, QUIESCENCE_DEPTH, deadline);
+    }
+    for mv in ordered_moves(board, false) {
+        let after = play(board, mv);
+        let score = -negamax(&after, depth - 1, -beta, -alpha, ply + 1, deadline);
+        if score >= beta {
+            return score;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+// Only captures, until the position is quiet, so the search doesn't stop
+// in the middle of an exchange
+fn quiesce(
+    board: &Board,
+    mut alpha: i32,
+    beta: i32,
+    ply: i32,
+    depth: u32,
+    deadline: Option<Instant>,
+) -> i32 {
+    if timed_out(deadline) {
+        return 0;
+    }
+    let stand_pat = evaluate(board);
+    if stand_pat >= beta || depth == 0 {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+    for mv in ordered_moves(board, true) {
+        let after = play(board, mv);
+        let score = match game_over_score(&after, ply + 1) {
+            Some(score) => -score,
+            None => -quiesce(&after, -beta, -alpha, ply + 1, depth - 1, deadline),
+        };
+        if score >= beta {
+            return score;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+// The score of a finished game for the side to move
+fn game_over_score(board: &Board, ply: i32) -> Option<i32> {
+    match board.game_state {
+        GameState::Checkmate => Some(-(MATE - ply)),
+        GameState::Won(color) if color == board.turn => Some(MATE - ply),
+        GameState::Won(_) => Some(-(MATE - ply)),
+        GameState::Stalemate | GameState::Draw | GameState::GameOver => Some(0),
+        GameState::Check | GameState::InProgress => None,
+    }
+}
+
+// The exact score of a position found in an endgame table
+fn table_score(distance: Distance, ply: i32) -> i32 {
+    match distance {
+        Distance::Win(plies) => MATE - (ply + plies as i32),
+        Distance::Loss(plies) => -(MATE - (ply + plies as i32)),
+        Distance::Draw => 0,
+    }
+}
+
+// Legal moves with the most valuable captures first, or only the captures
+fn ordered_moves(board: &Board, captures_only: bool) -> Vec<Move> {
+    let candidates: Vec<Move> = if captures_only {
+        // drops never take anything, so don't bother generating them
+        board
+            .legal_moves()
+            .into_iter()
+            .map(|(from, to)| (from, to, board.is_promotion(from, to).then_some('q')))
+            .collect()
+    } else {
+        board.all_moves()
+    };
+    let mut moves: Vec<(Move, i32)> = candidates
+        .into_iter()
+        .map(|mv| {
+            let (from, to, promotion) = mv;
+            let gain = board
+                .captured_piece(from, to)
+                .map_or(0, |piece| piece_value(piece.name) * 10 + 1)
+                + if from != to {
+                    promotion.map_or(0, piece_value)
+                } else {
+                    0
+                };
+            (mv, gain)
+        })
+        .filter(|&(_, gain)| !captures_only || gain > 0)
+        .collect();
+    moves.sort_by_key(|&(_, gain)| -gain);
+    moves.into_iter().map(|(mv, _)| mv).collect()
+}
+
+fn play(board: &Board, (from, to, promotion): Move) -> Board {
+    let mut after = board.clone();
+    after.make_move(from, to, promotion);
+    after
+}
@@ -0,0 +1,449 @@
+use crate::notation::piece_value;
+use crate::{Board, GameState, Piece, Variant};
+
+// A move as it was played, with what's needed to show it in a move list
+#[derive(Clone, Debug)]
+pub struct GameMove {
+    pub from: usize,
+    pub to: usize,
+    pub promotion: Option<char>,
+    pub san: String,
+    pub captured: Option<Piece>,
+}
+
+impl GameMove {
+    fn new(board: &Board, from: usize, to: usize, promotion: Option<char>) -> GameMove {
+        let drop = from == to;
+        GameMove {
+            from,
+            to,
+            // crazyhouse drops keep the piece that was dropped here
+            promotion: if drop {
+                promotion.map(|name| name.to_ascii_lowercase())
+            } else if board.is_promotion(from, to) {
+                Some(promotion.unwrap_or('q'))
+            } else {
+                None
+            },
+            san: board.san(from, to, promotion),
+            captured: board.captured_piece(from, to),
+        }
+    }
+}
+
+// The colors of arrows and marked squares, by their letter in the PGN
+// [%cal] and [%csl] commands
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+}
+
+impl Highlight {
+    pub fn letter(self) -> char {
+        match self {
+            Highlight::Green => 'G',
+            Highlight::Red => 'R',
+            Highlight::Yellow => 'Y',
+            Highlight::Blue => 'B',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Highlight> {
+        match letter {
+            'G' => Some(Highlight::Green),
+            'R' => Some(Highlight::Red),
+            'Y' => Some(Highlight::Yellow),
+            'B' => Some(Highlight::Blue),
+            _ => None,
+        }
+    }
+}
+
+// A position in the game tree and the move that led to it
+#[derive(Clone, Debug)]
+struct Node {
+    board: Board,
+    mv: Option<GameMove>,
+    parent: Option<usize>,
+    // the first child continues the line, the others are variations
+    children: Vec<usize>,
+    // PGN annotations of the move, like $2 for "?", and a comment after it
+    nags: Vec<u8>,
+    comment: String,
+    // drawn on the position, as (color, from, to) and (color, square)
+    arrows: Vec<(Highlight, usize, usize)>,
+    marks: Vec<(Highlight, usize)>,
+}
+
+// A game record as a tree of positions, node 0 is the start position.
+// `current` is the node being looked at and `line_end` the last node of the
+// line shown in the move list, so moving around never loses any moves.
+#[derive(Clone, Debug)]
+pub struct Game {
+    nodes: Vec<Node>,
+    current: usize,
+    line_end: usize,
+    // PGN tag pairs like ("Event", "Casual game"), in the order they are written
+    pub tags: Vec<(String, String)>,
+}
+
+impl Game {
+    pub fn new(board: Board) -> Game {
+        let variant = board.variant;
+        let mut game = Game {
+            nodes: vec![Node {
+                board,
+                mv: None,
+                parent: None,
+                children: vec![],
+                nags: vec![],
+                comment: String::new(),
+                arrows: vec![],
+                marks: vec![],
+            }],
+            current: 0,
+            line_end: 0,
+            tags: vec![],
+        };
+        if variant != Variant::Standard {
+            game.set_tag("Variant", variant.name());
+        }
+        game
+    }
+
+    // The position the game started from
+    pub fn start(&self) -> &Board {
+        &self.nodes[0].board
+    }
+
+    // The value of a PGN tag, e.g. tag("White")
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Sets a PGN tag, replacing an existing one with the same name
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(key, _)| key == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // "1-0", "0-1" or "1/2-1/2" once the main line is decided, "*" otherwise.
+    // A "Result" tag read from a PGN file wins over the position.
+    pub fn result(&self) -> String {
+        let last = &self.nodes[self.main_line_end(0)].board;
+        match last.game_state {
+            GameState::Checkmate if last.turn => "0-1".to_string(),
+            GameState::Checkmate => "1-0".to_string(),
+            GameState::Stalemate => "1/2-1/2".to_string(),
+            GameState::Won(true) => "1-0".to_string(),
+            GameState::Won(false) => "0-1".to_string(),
+            GameState::Draw => "1/2-1/2".to_string(),
+            _ => self.tag("Result").unwrap_or("*").to_string(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.nodes[self.current].board
+    }
+
+    pub fn board_mut(&mut self) -> &mut Board {
+        &mut self.nodes[self.current].board
+    }
+
+    // The moves of the line shown, from the start to its last move
+    pub fn moves(&self) -> Vec<GameMove> {
+        self.path(self.line_end)
+            .iter()
+            .filter_map(|&node| self.nodes[node].mv.clone())
+            .collect()
+    }
+
+    // How many moves of the line lead to the position shown
+    pub fn current(&self) -> usize {
+        self.path(self.current).len()
+    }
+
+    // Shows the position after `ply` moves of the line, 0 is the start position
+    pub fn go_to(&mut self, ply: usize) {
+        let path = self.path(self.line_end);
+        self.current = match ply.min(path.len()) {
+            0 => 0,
+            ply => path[ply - 1],
+        };
+        self.board_mut().selected = None;
+    }
+
+    // The node being looked at. Nodes are numbered in the order they were added.
+    pub fn node(&self) -> usize {
+        self.current
+    }
+
+    // Shows a node. When it's not on the line shown, its line becomes the one
+    // shown, following the main continuation from there.
+    pub fn go_to_node(&mut self, node: usize) {
+        if node != 0 && !self.path(self.line_end).contains(&node) {
+            self.line_end = self.main_line_end(node);
+        }
+        self.current = node;
+        self.board_mut().selected = None;
+    }
+
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        self.nodes[node].parent
+    }
+
+    // The moves played from a node, the first one continues the line
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.nodes[node].children
+    }
+
+    pub fn board_at(&self, node: usize) -> &Board {
+        &self.nodes[node].board
+    }
+
+    // The move leading to a node, None for the start position
+    pub fn move_at(&self, node: usize) -> Option<&GameMove> {
+        self.nodes[node].mv.as_ref()
+    }
+
+    // The comment after the move leading to a node, for node 0 the one
+    // before the first move. Empty when there is none.
+    pub fn comment(&self, node: usize) -> &str {
+        &self.nodes[node].comment
+    }
+
+    pub fn set_comment(&mut self, node: usize, comment: &str) {
+        self.nodes[node].comment = comment.to_string();
+    }
+
+    // The NAGs of the move leading to a node, e.g. 1 for "!" and 4 for "??"
+    pub fn nags(&self, node: usize) -> &[u8] {
+        &self.nodes[node].nags
+    }
+
+    pub fn add_nag(&mut self, node: usize, nag: u8) {
+        if !self.nodes[node].nags.contains(&nag) {
+            self.nodes[node].nags.push(nag);
+        }
+    }
+
+    // The arrows drawn on a node's position, as (color, from, to)
+    pub fn arrows(&self, node: usize) -> &[(Highlight, usize, usize)] {
+        &self.nodes[node].arrows
+    }
+
+    // The squares marked on a node's position
+    pub fn marks(&self, node: usize) -> &[(Highlight, usize)] {
+        &self.nodes[node].marks
+    }
+
+    // Draws an arrow, or takes it away when it's there in the same color.
+    // An arrow in another color changes color.
+    pub fn toggle_arrow(&mut self, node: usize, color: Highlight, from: usize, to: usize) {
+        let arrows = &mut self.nodes[node].arrows;
+        match arrows.iter().position(|&(_, f, t)| (f, t) == (from, to)) {
+            Some(i) if arrows[i].0 == color => {
+                arrows.remove(i);
+            }
+            Some(i) => arrows[i].0 = color,
+            None => arrows.push((color, from, to)),
+        }
+    }
+
+    // Marks a square the same way
+    pub fn toggle_mark(&mut self, node: usize, color: Highlight, square: usize) {
+        let marks = &mut self.nodes[node].marks;
+        match marks.iter().position(|&(_, s)| s == square) {
+            Some(i) if marks[i].0 == color => {
+                marks.remove(i);
+            }
+            Some(i) => marks[i].0 = color,
+            None => marks.push((color, square)),
+        }
+    }
+
+    pub fn clear_drawings(&mut self, node: usize) {
+        self.nodes[node].arrows.clear();
+        self.nodes[node].marks.clear();
+    }
+
+    // The nodes of the main line after `node`, following the first child
+    pub fn main_line(&self, node: usize) -> Vec<usize> {
+        let mut line = vec![];
+        let mut node = node;
+        while let Some(&next) = self.nodes[node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    // The alternatives to the next move of the line, in the order they were added
+    pub fn variations(&self) -> Vec<&GameMove> {
+        let next = self.next_on_line();
+        self.nodes[self.current]
+            .children
+            .iter()
+            .filter(|&&child| Some(child) != next)
+            .filter_map(|&child| self.nodes[child].mv.as_ref())
+            .collect()
+    }
+
+    // Switches to the variation after the one the line follows from the
+    // current position, and plays its first move
+    pub fn enter_variation(&mut self) -> bool {
+        let children = &self.nodes[self.current].children;
+        if children.len() < 2 {
+            return false;
+        }
+        let index = match self.next_on_line() {
+            Some(next) => children.iter().position(|&c| c == next).unwrap_or(0),
+            None => 0,
+        };
+        let child = children[(index + 1) % children.len()];
+        self.board_mut().selected = None;
+        self.line_end = self.main_line_end(child);
+        self.current = child;
+        true
+    }
+
+    // Goes back to where the variation being looked at branched off, and
+    // shows the line it was an alternative to
+    pub fn leave_variation(&mut self) -> bool {
+        let mut node = self.current;
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].children[0] != node {
+                self.board_mut().selected = None;
+                self.current = parent;
+                self.line_end = self.main_line_end(parent);
+                return true;
+            }
+            node = parent;
+        }
+        false
+    }
+
+    // Plays a move from the current position. A move that was already played
+    // from here is followed, a new one starts a variation. Crazyhouse drops
+    // go from the target square to itself, see Board::is_legal.
+    pub fn play(&mut self, from: usize, to: usize, promotion: Option<char>) -> bool {
+        let board = self.board();
+        if board.is_finished() || !board.is_legal(from, to, promotion) {
+            return false;
+        }
+        let mv = GameMove::new(board, from, to, promotion);
+        let mut next = board.clone();
+        next.make_move(from, to, promotion);
+        next.selected = None;
+        self.push(mv, next);
+        true
+    }
+
+    // Board::select_square on the current position, recording the move if one is made
+    pub fn select_square(&mut self, pos: usize) -> Option<(usize, usize)> {
+        let before = self.board().clone();
+        let mut next = before.clone();
+        let result = next.select_square(pos);
+        match result {
+            Some((from, to)) => {
+                let mv = GameMove::new(&before, from, to, None);
+                self.board_mut().selected = None;
+                self.push(mv, next);
+            }
+            None => *self.board_mut() = next,
+        }
+        result
+    }
+
+    fn push(&mut self, mv: GameMove, next: Board) {
+        let existing = self.nodes[self.current]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| match &self.nodes[child].mv {
+                Some(old) => (old.from, old.to, old.promotion) == (mv.from, mv.to, mv.promotion),
+                None => false,
+            });
+        let node = match existing {
+            Some(node) => node,
+            None => {
+                self.nodes.push(Node {
+                    board: next,
+                    mv: Some(mv),
+                    parent: Some(self.current),
+                    children: vec![],
+                    nags: vec![],
+                    comment: String::new(),
+                    arrows: vec![],
+                    marks: vec![],
+                });
+                let node = self.nodes.len() - 1;
+                self.nodes[self.current].children.push(node);
+                node
+            }
+        };
+        self.go_to_node(node);
+    }
+
+    // Pieces of `color` that have been captured on the way to the current position
+    pub fn captured(&self, color: bool) -> Vec<Piece> {
+        self.path(self.current)
+            .iter()
+            .filter_map(|&node| self.nodes[node].mv.as_ref())
+            .filter_map(|mv| mv.captured.clone())
+            .filter(|piece| piece.color() == color)
+            .collect()
+    }
+
+    // Material balance in points at the current position, positive when white is ahead
+    pub fn material_difference(&self) -> i32 {
+        self.board()
+            .board
+            .iter()
+            .flatten()
+            .map(|piece| {
+                let value = piece_value(piece.name);
+                if piece.color() {
+                    value
+                } else {
+                    -value
+                }
+            })
+            .sum()
+    }
+
+    // The nodes from the first move to `node`, the start position left out
+    fn path(&self, node: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut node = node;
+        while let Some(parent) = self.nodes[node].parent {
+            path.push(node);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    // The last node reached from `node` by always taking the first child
+    fn main_line_end(&self, node: usize) -> usize {
+        let mut node = node;
+        while let Some(&child) = self.nodes[node].children.first() {
+            node = child;
+        }
+        node
+    }
+
+    // The node after the current one on the line shown
+    fn next_on_line(&self) -> Option<usize> {
+        let path = self.path(self.line_end);
+        path.get(self.current()).copied()
+    }
+}
@@ -0,0 +1,70 @@
+use crate::Board;
+
+// Boards can be other sizes than 8x8, from 4 up to MAX_SIZE files and ranks.
+// Squares are numbered row by row from the top left as white sees the board,
+// so 0 is a8 on a standard board and a10 on a 10x10 one, and the last square
+// is the bottom right corner.
+pub const MAX_SIZE: usize = 16;
+
+impl Board {
+    // Number of squares
+    pub fn size(&self) -> usize {
+        self.width * self.height
+    }
+
+    // File of a square, 0 is the a file
+    pub fn file(&self, pos: usize) -> usize {
+        pos % self.width
+    }
+
+    // Row of a square counted from the top, 0 is black's back rank
+    pub fn row(&self, pos: usize) -> usize {
+        pos / self.width
+    }
+
+    // The square on a file and row, None when that's off the board
+    pub fn square(&self, file: i32, row: i32) -> Option<usize> {
+        let inside =
+            (0..self.width as i32).contains(&file) && (0..self.height as i32).contains(&row);
+        inside.then(|| row as usize * self.width + file as usize)
+    }
+
+    // The row of a color's back rank
+    pub fn back_row(&self, color: bool) -> usize {
+        if color {
+            self.height - 1
+        } else {
+            0
+        }
+    }
+
+    // Algebraic name of a square, e.g. "e4" or "j10"
+    pub fn square_name(&self, pos: usize) -> String {
+        let file = (b'a' + self.file(pos) as u8) as char;
+        format!("{}{}", file, self.height - self.row(pos))
+    }
+
+    // The square named at the start of `text` and the text after it, so
+    // "e2e4" gives e2 and "e4"
+    pub fn read_square<'a>(&self, text: &'a str) -> Option<(usize, &'a str)> {
+        let file = text.chars().next().filter(|c| c.is_ascii_lowercase())?;
+        let digits = text[1..].len()
+            - text[1..]
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let rank: usize = text[1..1 + digits].parse().ok()?;
+        if !(1..=self.height).contains(&rank) {
+            return None;
+        }
+        let pos = self.square(file as i32 - 'a' as i32, (self.height - rank) as i32)?;
+        Some((pos, &text[1 + digits..]))
+    }
+
+    // Square index from its algebraic name, the inverse of square_name
+    pub fn parse_square(&self, name: &str) -> Option<usize> {
+        match self.read_square(name) {
+            Some((pos, "")) => Some(pos),
+            _ => None,
+        }
+    }
+}
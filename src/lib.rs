@@ -1,381 +1,914 @@
 use std::{collections::HashMap, vec};
 
-#[derive(Clone)]
+pub mod analysis;
+pub mod book;
+mod bughouse;
+mod chess960;
+mod crazyhouse;
+mod display;
+pub mod engine;
+mod game;
+mod geometry;
+pub mod mate;
+mod notation;
+mod pgn;
+mod pieces;
+pub mod puzzle;
+pub mod retrograde;
+mod setup;
+mod variant;
+
+pub use bughouse::{Bughouse, Outcome};
+pub use chess960::{chess960_fen, random_chess960};
+pub use display::{BoardDisplay, DisplayOptions};
+pub use game::{Game, GameMove, Highlight};
+pub use geometry::MAX_SIZE;
+pub use notation::piece_value;
+pub use pieces::{
+    define_piece, load_pieces, parse_betza, piece_def, piece_letters, MoveMode, Movement, PieceDef,
+};
+pub use variant::{Variant, VARIANTS};
+
+#[derive(Clone, Debug)]
 pub struct Piece {
     pub name: char,
-    moves: Vec<i32>,
-    max_steps: usize,
+    movements: Vec<Movement>,
     color: bool,
     has_moved: bool,
+    // a pawn that promoted, crazyhouse gives it back as a pawn when captured
+    promoted: bool,
 }
 impl Piece {
-    fn new(name: char, moves: Vec<i32>, max_steps: usize, color: bool) -> Piece {
-        return Piece {
+    fn new(name: char, movements: Vec<Movement>, color: bool) -> Piece {
+        Piece {
             name,
-            moves,
-            max_steps,
+            movements,
             color,
             has_moved: false,
-        };
+            promoted: false,
+        }
+    }
+
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    pub fn promoted(&self) -> bool {
+        self.promoted
+    }
+
+    //Creates a piece from its FEN letter, uppercase is white. The letter has to
+    //be one of the defined pieces, see the pieces module.
+    pub fn from_char(c: char) -> Option<Piece> {
+        if !c.is_ascii_alphabetic() {
+            return None;
+        }
+        let def = piece_def(c)?;
+        Some(Piece::new(c, def.movements, c.is_ascii_uppercase()))
+    }
+
+    // Unicode chess glyph, white pieces use the outlined symbols
+    pub fn glyph(&self) -> char {
+        unicode_glyph(self.name)
     }
 }
-#[derive(Clone, PartialEq)]
+
+impl std::fmt::Display for Piece {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub fn unicode_glyph(name: char) -> char {
+    match name {
+        // White pieces
+        'P' => '\u{2659}', // White Pawn
+        'R' => '\u{2656}', // White Rook
+        'N' => '\u{2658}', // White Knight
+        'B' => '\u{2657}', // White Bishop
+        'Q' => '\u{2655}', // White Queen
+        'K' => '\u{2654}', // White King
+
+        // Black pieces
+        'p' => '\u{265F}', // Black Pawn
+        'r' => '\u{265C}', // Black Rook
+        'n' => '\u{265E}', // Black Knight
+        'b' => '\u{265D}', // Black Bishop
+        'q' => '\u{265B}', // Black Queen
+        'k' => '\u{265A}', // Black King
+        // fairy pieces have no glyph of their own
+        _ => name,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum GameState {
     InProgress,
     Check,
     Checkmate,
+    Stalemate,
     GameOver,
+    // a variant rule decided the game, for the color given
+    Won(bool),
+    Draw,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Board {
-    pub board: [Option<Piece>; 64],
+    // the squares row by row from the top, see the geometry module
+    pub board: Vec<Option<Piece>>,
+    pub width: usize,
+    pub height: usize,
     pub turn: bool,
     pub selected: Option<usize>,
     pub game_state: GameState,
-    check_moves: Option<Vec<(usize, usize)>>,
+    pub last_move: Option<(usize, usize)>,
+    // squares of the rooks that can still castle
+    pub castling: Vec<usize>,
+    // square a pawn can capture en passant on, right after a double step
+    pub en_passant: Option<usize>,
+    // moves since the last capture or pawn move, and the move number as in FEN
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub variant: Variant,
+    // checks given by white and black, for three-check
+    pub checks: [u32; 2],
+    // crazyhouse pieces in hand as FEN letters, uppercase for white's
+    pub pocket: Vec<char>,
 }
 impl Board {
-    pub fn new(board: [Option<Piece>; 64], turn: bool) -> Board {
-        return Board {
+    // A board `width` files wide, the squares make up as many ranks as they fill
+    pub fn new(board: Vec<Option<Piece>>, width: usize, turn: bool) -> Board {
+        let height = board.len() / width;
+        Board {
             board,
+            width,
+            height,
             turn,
             selected: None,
             game_state: GameState::InProgress,
-            check_moves: None,
-        };
+            last_move: None,
+            castling: vec![],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pocket: vec![],
+        }
     }
 
     pub fn get_piece(&self, pos: usize) -> Option<Piece> {
-        return self.board[pos].clone();
+        self.board[pos].clone()
     }
 
     //keeps track of all moves
-    pub fn get_moves(&mut self) -> HashMap<usize, Vec<usize>> {
+    pub fn get_moves(&self) -> HashMap<usize, Vec<usize>> {
         let mut moves_map: HashMap<usize, Vec<usize>> = HashMap::new();
-        for i in 0..64 {
+        for i in 0..self.size() {
             if let Some(_piece) = &self.get_piece(i) {
                 moves_map.insert(i, self.get_valid_moves(i));
             }
         }
-        return moves_map;
+        moves_map
     }
 
-    //Check for bounds
+    //Check for bounds: whether `to` lies on one of the lines the piece on `pos`
+    //moves along, as far as its range goes and ignoring other pieces
     pub fn valid_move_in_bounds(&self, pos: usize, to: usize) -> bool {
-        let current_row = pos / 8; // Current row (0-7)
-        let current_col = pos % 8; // Current column (0-7)
-        let mut legal: bool = false;
-
-        let new = to;
-        let new_row = new / 8;
-        let new_col = new % 8;
-        let name = self.get_piece(pos).unwrap().name.to_ascii_lowercase();
-
-        match name {
-            'r' => {
-                legal = current_row == new_row || current_col == new_col;
-            }
-            'b' => {
-                legal = (current_row as i32 - new_row as i32).abs()
-                    == (current_col as i32 - new_col as i32).abs();
+        match &self.board[pos] {
+            Some(piece) => piece
+                .movements
+                .iter()
+                .any(|movement| self.line(pos, piece.color, movement).contains(&to)),
+            None => false,
+        }
+    }
+
+    //Squares a piece could go to if its own king didn't matter
+    fn pseudo_moves(&self, pos: usize) -> Vec<usize> {
+        let mut moves: Vec<usize> = vec![];
+        let piece = match &self.board[pos] {
+            Some(piece) => piece,
+            None => return moves,
+        };
+        let is_pawn = piece.name.eq_ignore_ascii_case(&'p');
+        // atomic kings can't capture, they would blow themselves up
+        let captures = !(self.variant == Variant::Atomic && piece.name.eq_ignore_ascii_case(&'k'));
+        for movement in &piece.movements {
+            for to in self.ray(pos, piece.color, movement) {
+                match &self.board[to] {
+                    Some(target)
+                        if captures
+                            && target.color != piece.color
+                            && movement.mode != MoveMode::MoveOnly =>
+                    {
+                        moves.push(to)
+                    }
+                    Some(_) => {}
+                    None if movement.mode != MoveMode::CaptureOnly => moves.push(to),
+                    // pawns capture en passant onto the empty square behind the pawn
+                    None if is_pawn && self.en_passant == Some(to) => moves.push(to),
+                    None => {}
+                }
             }
-            'q' => {
-                legal = (current_row == new_row || current_col == new_col)
-                    || (current_row as i32 - new_row as i32).abs()
-                        == (current_col as i32 - new_col as i32).abs();
+        }
+        // and push two squares from their start rank, in horde from the first rank too
+        let from_back = self.row(pos).abs_diff(self.back_row(piece.color));
+        let start_rank = self.variant.double_step_rank() == Some(from_back);
+        let horde_rank = self.variant == Variant::Horde && piece.color && from_back == 0;
+        if is_pawn && (start_rank || horde_rank) {
+            let forward = if piece.color { -1 } else { 1 };
+            let (file, row) = (self.file(pos) as i32, self.row(pos) as i32);
+            let one = self.square(file, row + forward);
+            let two = self.square(file, row + 2 * forward);
+            if let (Some(one), Some(two)) = (one, two) {
+                if self.board[one].is_none() && self.board[two].is_none() {
+                    moves.push(two);
+                }
             }
-            'n' => {
-                let row_diff = (current_row as i32 - new_row as i32).abs();
-                let col_diff = (current_col as i32 - new_col as i32).abs();
-                legal = (row_diff == 2 && col_diff == 1) || (row_diff == 1 && col_diff == 2);
+        }
+        moves
+    }
+
+    //The squares a movement reaches from `pos`, up to and including the first
+    //occupied one
+    fn ray(&self, pos: usize, color: bool, movement: &Movement) -> Vec<usize> {
+        let mut squares = self.line(pos, color, movement);
+        if let Some(i) = squares.iter().position(|&sq| self.board[sq].is_some()) {
+            squares.truncate(i + 1);
+        }
+        squares
+    }
+
+    //Every square attacked by the pieces of one color, whoever's turn it is
+    pub fn attacked_squares(&self, color: bool) -> Vec<usize> {
+        let mut attacked = vec![];
+        for pos in 0..self.size() {
+            match &self.board[pos] {
+                Some(piece) if piece.color == color => attacked.extend(self.attacks_from(pos)),
+                _ => {}
             }
-            'p' => {
-                legal = (current_row as i32 - new_row as i32).abs()
-                    == (current_col as i32 - new_col as i32).abs()
-                    || current_col == new_col
+        }
+        attacked.sort_unstable();
+        attacked.dedup();
+        attacked
+    }
+
+    //Like pseudo_moves, but squares defended by the piece count too
+    fn attacks_from(&self, pos: usize) -> Vec<usize> {
+        let mut attacks = vec![];
+        if let Some(piece) = &self.board[pos] {
+            if self.variant == Variant::Atomic && piece.name.eq_ignore_ascii_case(&'k') {
+                return attacks;
             }
-            'k' => {
-                legal = (current_row == new_row || current_col == new_col)
-                    || (current_row as i32 - new_row as i32).abs()
-                        == (current_col as i32 - new_col as i32).abs();
+            for movement in &piece.movements {
+                if movement.mode != MoveMode::MoveOnly {
+                    attacks.extend(self.ray(pos, piece.color, movement));
+                }
             }
+        }
+        attacks
+    }
+
+    pub fn king_square(&self, color: bool) -> Option<usize> {
+        (0..self.size()).find(|&pos| match &self.board[pos] {
+            Some(piece) => piece.color == color && piece.name.eq_ignore_ascii_case(&'k'),
+            None => false,
+        })
+    }
+
+    pub fn in_check(&self, color: bool) -> bool {
+        match self.variant {
+            Variant::Antichess => return false,
+            Variant::Atomic if self.kings_touch() => return false,
             _ => {}
         }
-        return legal;
+        match self.king_square(color) {
+            Some(king) => self.attacked_squares(!color).contains(&king),
+            None => false,
+        }
     }
 
-    //Returns all valid tiles to move to for a certain piece
-    pub fn get_valid_moves(&mut self, pos: usize) -> Vec<usize> {
-        let mut moves: Vec<usize> = vec![];
-        if let Some(mut piece) = self.get_piece(pos).clone() {
-            //add another step on first move for pawns
-            if piece.name.to_ascii_lowercase() == 'p' {
-                if !piece.has_moved {
-                    piece.max_steps = 2;
-                } else {
-                    piece.max_steps = 1;
-                }
+    //Whether a move leaves the own king in check, or breaks another rule of
+    //the variant about the position after it
+    fn result_in_check(&self, from: usize, to: usize) -> bool {
+        let mut cloned_board = self.clone();
+        let color = self.board[from].as_ref().map_or(self.turn, |p| p.color);
+        cloned_board.apply_move(from, to, None);
+        !cloned_board.allowed_after_move(color)
+    }
+
+    //Castling destinations for the king on `king`. The king ends next to the
+    //corner or on the c file and the rook next to it, see castling_targets.
+    //Every square in between has to be empty and the king can't be in check
+    //or pass through an attacked square. Chess960 castling is written as the
+    //king taking its own rook, unless king and rook start on their usual
+    //squares.
+    fn castling_moves(&self, king: usize) -> Vec<usize> {
+        let mut moves = vec![];
+        let color = match &self.board[king] {
+            Some(piece) if self.variant != Variant::Antichess => piece.color,
+            _ => return moves,
+        };
+        let row = self.row(king);
+        let attacked = self.attacked_squares(!color);
+        if attacked.contains(&king) {
+            return moves;
+        }
+        for &rook in &self.castling {
+            match &self.board[rook] {
+                Some(piece) if piece.color == color && piece.name.eq_ignore_ascii_case(&'r') => {}
+                _ => continue,
             }
-            for mv in piece.moves {
-                for i in 1..=piece.max_steps {
-                    if piece.name.to_ascii_lowercase() == 'p' {
-                        // pawn logic with attacks
-                        let attack_to: i32 = 1 + pos as i32 + mv as i32;
-                        if let Some(target) = self.get_piece(attack_to as usize) {
-                            if attack_to > 0
-                                && self.valid_move(pos, attack_to as usize) != 0
-                                && target.color != piece.color
-                            {
-                                moves.push(attack_to as usize);
-                            }
-                        }
-                        let attack_to: i32 = -1 + pos as i32 + mv as i32;
-                        if let Some(target) = self.get_piece(attack_to as usize) {
-                            if attack_to > 0
-                                && self.valid_move(pos, attack_to as usize) != 0
-                                && target.color != piece.color
-                            {
-                                moves.push(attack_to as usize);
-                            }
-                        }
-                        let to: i32 = pos as i32 + mv * i as i32;
-                        if to > 0 && self.valid_move(pos, to as usize) == 1 {
-                            moves.push(to as usize);
-                        } else {
-                            break;
-                        }
-                    } else {
-                        // allow for captures on normal moves
-                        let to: i32 = pos as i32 + mv * i as i32;
-                        if to > 0 && self.valid_move(pos, to as usize) != 0
-                        // && !self.result_in_mate(pos, to as usize)
-                        {
-                            moves.push(to as usize);
-                            if self.valid_move(pos, to as usize) == 2 {
-                                break;
-                            }
-                        } else {
-                            break;
+            if self.row(rook) != row {
+                continue;
+            }
+            let (king_to, rook_to) = self.castling_targets(king, rook);
+            let span = |a: usize, b: usize| a.min(b)..=a.max(b);
+            let blocked = span(king, king_to)
+                .chain(span(rook, rook_to))
+                .any(|sq| sq != king && sq != rook && self.board[sq].is_some());
+            let through_check = span(king, king_to).any(|sq| attacked.contains(&sq));
+            let usual = self.file(king) == self.width / 2
+                && (self.file(rook) == 0 || self.file(rook) == self.width - 1);
+            let to = if usual { king_to } else { rook };
+            // the rook can be what shielded the king's destination
+            if !blocked && !through_check && !self.result_in_check(king, to) {
+                moves.push(to);
+            }
+        }
+        moves
+    }
+
+    //Returns all valid tiles to move to for a certain piece
+    pub fn get_valid_moves(&self, pos: usize) -> Vec<usize> {
+        match &self.board[pos] {
+            Some(piece) if piece.color == self.turn => {
+                let mut moves: Vec<usize> = self
+                    .pseudo_moves(pos)
+                    .into_iter()
+                    .filter(|&to| !self.result_in_check(pos, to))
+                    .collect();
+                if piece.name.eq_ignore_ascii_case(&'k') {
+                    for to in self.castling_moves(pos) {
+                        if !moves.contains(&to) {
+                            moves.push(to);
                         }
                     }
                 }
+                if self.variant == Variant::Antichess && self.must_capture() {
+                    moves.retain(|&to| self.is_capture(pos, to));
+                }
+                moves
             }
+            _ => vec![],
         }
-        moves
     }
 
-    fn result_in_check(&self, from: usize, to: usize) -> bool {
-        //TODO
-        return true;
+    //The rook square of a castling move, if the move is one. The move is either
+    //the king going to its destination or the king taking its own rook.
+    pub fn castling_rook(&self, from: usize, to: usize) -> Option<usize> {
+        let color = match &self.board[from] {
+            Some(piece) if piece.name.eq_ignore_ascii_case(&'k') => piece.color,
+            _ => return None,
+        };
+        if self.row(from) != self.row(to) {
+            return None;
+        }
+        let own_rook = match &self.board[to] {
+            Some(piece) => piece.color == color && piece.name.eq_ignore_ascii_case(&'r'),
+            None => false,
+        };
+        if own_rook {
+            return Some(to).filter(|rook| self.castling.contains(rook));
+        }
+        if self.file(from).abs_diff(self.file(to)) < 2 {
+            return None;
+        }
+        self.castling.iter().copied().find(|&rook| {
+            self.row(rook) == self.row(from)
+                && (rook > from) == (to > from)
+                && self.castling_targets(from, rook).0 == to
+        })
     }
 
-    fn result_in_mate(&mut self, from: usize, to: usize) -> bool {
-        let mut cloned_board = self.clone();
+    //The rook furthest from the king on one side of it, on the king's back rank
+    pub fn outermost_rook(&self, color: bool, king_side: bool) -> Option<usize> {
+        let rank = self.back_row(color) * self.width;
+        let king = self
+            .king_square(color)
+            .filter(|&k| self.row(k) == self.back_row(color))?;
+        let rook = if color { 'R' } else { 'r' };
+        let is_rook = |pos: &usize| matches!(&self.board[*pos], Some(p) if p.name == rook);
+        if king_side {
+            (king + 1..rank + self.width).rev().find(is_rook)
+        } else {
+            (rank..king).find(is_rook)
+        }
+    }
 
-        cloned_board.board[to] = cloned_board.board[from].clone();
-        cloned_board.board[from] = None;
-        cloned_board.turn = !cloned_board.turn;
-        if let Some(mvs) = cloned_board.check_moves.take() {
-            for mv in mvs {
-                if cloned_board.get_valid_moves(mv.0).contains(&mv.1) {
-                    return true;
-                }
+    //A pawn move onto the last rank
+    pub fn is_promotion(&self, from: usize, to: usize) -> bool {
+        match &self.board[from] {
+            Some(piece) => {
+                piece.name.eq_ignore_ascii_case(&'p')
+                    && (self.row(to) == 0 || self.row(to) == self.height - 1)
             }
+            None => false,
         }
-        return false;
     }
 
-    //Check if a move is valid (right turn and in bounds), return captures etc.
-    fn valid_move(&mut self, from: usize, to: usize) -> usize {
-        if !self.valid_move_in_bounds(from, to) || to >= 64 || self.result_in_mate(from, to) {
-            return 0;
+    //Whether a move is legal for the side to move. A crazyhouse drop is a move
+    //from the target square to itself with the piece letter as `promotion`.
+    pub fn is_legal(&self, from: usize, to: usize, promotion: Option<char>) -> bool {
+        if from == to {
+            return promotion.is_some_and(|name| self.can_drop(name, to));
         }
-        let from_piece = self.get_piece(from).unwrap();
-        if from_piece.color == self.turn {
-            if let Some(to_piece) = &self.get_piece(to) {
-                if to_piece.color != from_piece.color {
-                    //Capture
-                    return 2;
-                }
-            } else {
-                //check for checkmate and etc.
-                return 1;
+        from < self.size() && self.get_valid_moves(from).contains(&to)
+    }
+
+    //The piece a move takes, also en passant. Castling takes nothing, even
+    //when it's written as the king taking its own rook.
+    pub fn captured_piece(&self, from: usize, to: usize) -> Option<Piece> {
+        if from == to || self.castling_rook(from, to).is_some() {
+            return None;
+        }
+        let pawn =
+            matches!(&self.board[from], Some(piece) if piece.name.eq_ignore_ascii_case(&'p'));
+        match self.en_passant {
+            Some(sq) if sq == to && pawn && self.file(from) != self.file(to) => {
+                self.board[self.row(from) * self.width + self.file(to)].clone()
             }
+            _ => self.board[to].clone(),
         }
-        return 0;
     }
 
-    pub fn move_piece(&mut self, from: usize, to: usize) {
-        let piece_clone;
-        if let Some(piece) = &mut self.board[from] {
-            piece.has_moved = true;
-            piece_clone = piece.clone();
-        } else {
-            return;
+    //Every legal move as (from, to, promotion), with each promotion piece and
+    //drop spelled out
+    pub fn all_moves(&self) -> Vec<(usize, usize, Option<char>)> {
+        let mut moves = vec![];
+        for (from, to) in self.legal_moves() {
+            if self.is_promotion(from, to) {
+                moves.extend(self.promotion_pieces().iter().map(|&p| (from, to, Some(p))));
+            } else {
+                moves.push((from, to, None));
+            }
+        }
+        for (name, to) in self.drops() {
+            moves.push((to, to, Some(name.to_ascii_lowercase())));
         }
+        moves
+    }
 
-        match self.valid_move(from, to) {
-            0 => {
-                println!("Invalid move!, {} {}", self.turn, piece_clone.color);
+    //All legal moves for the side to move
+    pub fn legal_moves(&self) -> Vec<(usize, usize)> {
+        let mut moves = vec![];
+        for from in 0..self.size() {
+            for to in self.get_valid_moves(from) {
+                moves.push((from, to));
             }
-            1 => {
-                //Normal move
-                self.board[to] = Some(piece_clone.clone());
-                self.board[from] = None;
-                self.check_check(to);
-                self.turn = !self.turn;
+        }
+        moves
+    }
+
+    //Moves a piece, pawns reaching the last rank become queens. Returns false
+    //and leaves the board alone when the move isn't legal.
+    pub fn move_piece(&mut self, from: usize, to: usize) -> bool {
+        self.make_move(from, to, None)
+    }
+
+    //Moves a piece, `promotion` is the piece letter a pawn turns into on the last
+    //rank. Returns false and leaves the board alone when the move isn't legal,
+    //reporting it is up to the caller.
+    pub fn make_move(&mut self, from: usize, to: usize, promotion: Option<char>) -> bool {
+        if !self.is_legal(from, to, promotion) {
+            return false;
+        }
+        match promotion {
+            Some(name) if from == to => self.apply_drop(name, to),
+            _ => self.apply_move(from, to, promotion),
+        }
+        if !self.turn {
+            self.fullmove_number += 1;
+        }
+        self.turn = !self.turn;
+        if self.in_check(self.turn) {
+            self.checks[if self.turn { 1 } else { 0 }] += 1;
+        }
+        self.update_state();
+        true
+    }
+
+    //Moves the pieces without any checks, including the rook when castling,
+    //the captured pawn en passant and promotions
+    fn apply_move(&mut self, from: usize, to: usize, promotion: Option<char>) {
+        let castling_rook = self.castling_rook(from, to);
+        let mut piece = match self.board[from].take() {
+            Some(piece) => piece,
+            None => return,
+        };
+        let is_pawn = piece.name.eq_ignore_ascii_case(&'p');
+        let capture = castling_rook.is_none() && self.board[to].is_some();
+        let en_passant =
+            is_pawn && !capture && Some(to) == self.en_passant && self.file(from) != self.file(to);
+        if self.variant == Variant::Crazyhouse && capture {
+            if let Some(captured) = self.board[to].clone() {
+                self.pocket_capture(&captured);
             }
-            2 => {
-                //Capture
-                let p = self.board[to].clone().unwrap();
-                self.board[to] = Some(piece_clone.clone());
-                self.board[from] = None;
-                self.check_check(to);
-                self.turn = !self.turn;
-                if p.name.to_ascii_lowercase() == 'k' {
-                    self.game_state = GameState::GameOver;
+        }
+
+        let mut to = to;
+        if let Some(rook) = castling_rook {
+            // in Chess960 the king and rook can end up on each other's squares
+            let (king_to, rook_to) = self.castling_targets(from, rook);
+            let rook_piece = self.board[rook].take();
+            self.board[rook_to] = rook_piece;
+            to = king_to;
+        }
+        if en_passant {
+            // the captured pawn is beside the moving one, not on the target square
+            let captured = self.row(from) * self.width + self.file(to);
+            if let Some(pawn) = self.board[captured].take() {
+                if self.variant == Variant::Crazyhouse {
+                    self.pocket_capture(&pawn);
                 }
             }
-            _ => {}
+        }
+
+        self.en_passant = None;
+        if is_pawn && from.abs_diff(to) == 2 * self.width {
+            self.en_passant = Some((from + to) / 2);
+        }
+        if is_pawn || capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        // moving the king or a rook, or capturing a rook, loses those castling rights
+        let king_moved = piece.name.eq_ignore_ascii_case(&'k');
+        let color = piece.color;
+        let (width, white_row) = (self.width, self.back_row(true));
+        self.castling.retain(|&rook| {
+            rook != from && rook != to && !(king_moved && (rook / width == white_row) == color)
+        });
+
+        if is_pawn && (self.row(to) == 0 || self.row(to) == self.height - 1) {
+            let name = promotion.unwrap_or('q');
+            let name = if color {
+                name.to_ascii_uppercase()
+            } else {
+                name.to_ascii_lowercase()
+            };
+            if let Some(promoted) = Piece::from_char(name) {
+                piece = promoted;
+                piece.promoted = true;
+            }
+        }
+        piece.has_moved = true;
+        self.board[to] = Some(piece);
+        self.last_move = Some((from, to));
+        if self.variant == Variant::Atomic && (capture || en_passant) {
+            self.explode(to);
         }
     }
-    fn check_check(&mut self, from: usize) {
-        for to in self.get_valid_moves(from) {
-            if self.valid_move(from, to) == 2 {
-                if let Some(p) = &self.board[to] {
-                    if p.name.to_ascii_lowercase() == 'k'
-                        && p.color != self.board[from].clone().unwrap().color
-                    {
-                        self.game_state = GameState::Check;
 
-                        if let Some(check_moves) = self.check_moves.as_mut() {
-                            check_moves.push((from, to));
-                        } else {
-                            self.check_moves = Some(vec![(from, to)]);
-                        }
-                        println!("Check {} -> {} ", from, to);
-                    }
-                }
+    //Checks and mates for the side to move
+    fn update_state(&mut self) {
+        if let Some(state) = self.variant_end() {
+            self.game_state = state;
+            return;
+        }
+        let in_check = self.in_check(self.turn);
+        let can_move = (0..self.size()).any(|pos| !self.get_valid_moves(pos).is_empty())
+            || (self.variant.has_drops() && !self.drops().is_empty());
+        self.game_state = match (in_check, can_move) {
+            (true, true) => GameState::Check,
+            (true, false) => GameState::Checkmate,
+            (false, true) => GameState::InProgress,
+            (false, false) => GameState::Stalemate,
+        };
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.game_state,
+            GameState::Checkmate
+                | GameState::Stalemate
+                | GameState::GameOver
+                | GameState::Won(_)
+                | GameState::Draw
+        )
+    }
+
+    //Click-style input shared by the front ends: selects a square, or moves the
+    //selected piece there if it's a valid move. Returns the move that was made.
+    pub fn select_square(&mut self, pos: usize) -> Option<(usize, usize)> {
+        if self.is_finished() {
+            return None;
+        }
+        if let Some(sq) = self.selected {
+            if sq == pos {
+                self.selected = None;
+            } else if self.board[sq].is_some() && self.get_valid_moves(sq).contains(&pos) {
+                self.move_piece(sq, pos);
+                self.selected = None;
+                return Some((sq, pos));
+            } else {
+                self.selected = Some(pos);
+            }
+        } else {
+            self.selected = Some(pos);
+        }
+        None
+    }
+
+    //Where the king and rook end up when castling: next to the corner on the
+    //king side, the g and f file on a standard board, and on the c and d file
+    //on the queen side
+    pub(crate) fn castling_targets(&self, king: usize, rook: usize) -> (usize, usize) {
+        let rank = self.row(king) * self.width;
+        if rook > king {
+            (rank + self.width - 2, rank + self.width - 3)
+        } else {
+            (rank + 2, rank + 3)
+        }
+    }
+
+    //Every square along a movement from `pos` until the edge of the board or the
+    //end of its range. Forward is up the board for white and down for black.
+    fn line(&self, pos: usize, color: bool, movement: &Movement) -> Vec<usize> {
+        let (files, ranks) = movement.step;
+        let (df, dr) = if color {
+            (files, -ranks)
+        } else {
+            (-files, ranks)
+        };
+        let range = if movement.range == 0 {
+            self.width.max(self.height)
+        } else {
+            movement.range
+        };
+        let mut squares = vec![];
+        let (mut file, mut row) = (self.file(pos) as i32, self.row(pos) as i32);
+        for _ in 0..range {
+            file += df;
+            row += dr;
+            match self.square(file, row) {
+                Some(sq) => squares.push(sq),
+                None => break,
             }
         }
+        squares
     }
 }
 
+//Square index from its algebraic name on a standard 8x8 board, the inverse
+//of square_name. Other sizes have Board::parse_square.
+pub fn parse_square(name: &str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1])
+    {
+        return None;
+    }
+    Some((bytes[0] - b'a') as usize + (b'8' - bytes[1]) as usize * 8)
+}
+
+//Algebraic name of a square on a standard 8x8 board, 0 is a8 and 63 is h1.
+//Other sizes have Board::square_name.
+pub fn square_name(pos: usize) -> String {
+    let file = (b'a' + (pos % 8) as u8) as char;
+    let rank = 8 - pos / 8;
+    format!("{}{}", file, rank)
+}
+
 pub fn create_board(fen_string: Option<&str>) -> Result<Board, String> {
-    let mut fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-    if fen_string.is_some() {
-        fen = fen_string.unwrap();
+    create_variant_board(Variant::Standard, fen_string)
+}
+
+//A board for a variant, from its start position when no FEN is given.
+//Three-check FENs have a check counter, see variant::parse_check_counter.
+pub fn create_variant_board(variant: Variant, fen_string: Option<&str>) -> Result<Board, String> {
+    let fen = fen_string.unwrap_or(variant.start_fen());
+    let mut fen_parts: Vec<&str> = fen.split_whitespace().collect();
+    let mut checks = [0, 0];
+    if variant == Variant::ThreeCheck && fen_parts.len() == 7 {
+        // "3+3" after the en passant square, or "+0+0" at the end
+        let counter = if fen_parts[6].starts_with('+') { 6 } else { 4 };
+        checks = variant::parse_check_counter(fen_parts.remove(counter))?;
     }
-    let fen_parts: Vec<&str> = fen.split_whitespace().collect();
     if fen_parts.len() != 6 {
         return Err("Invalid FEN-string: There should be 6 parts".to_string());
     }
-    const ARRAY_REPEAT_VALUE: Option<Piece> = None;
-    let mut board: [Option<Piece>; 64] = [ARRAY_REPEAT_VALUE; 64];
-    let board_fen = fen_parts[0];
-
-    let mut width = 0;
-    let mut height = 0;
-    for c in board_fen.chars() {
-        match c {
-            '/' => {
-                if width != 8 {
-                    return Err(
-                        "Invalid FEN-string: Incorrect number of squares in rank".to_string()
-                    );
-                }
-                height += 1;
-                width = 0;
-            }
-            '1'..='8' => {
-                if let Some(count) = c.to_digit(10) {
-                    let pos = width + height * 8;
-                    for _ in 0..count {
-                        board[pos] = None;
-                        width += 1;
+    // crazyhouse pockets follow the pieces in brackets, e.g. [Qnp]
+    let (board_fen, pocket_fen) = match fen_parts[0].split_once('[') {
+        Some((pieces, pocket)) => match pocket.strip_suffix(']') {
+            Some(pocket) => (pieces, pocket),
+            None => return Err("Invalid FEN-string: Unclosed pocket".to_string()),
+        },
+        None => (fen_parts[0], ""),
+    };
+    let mut pocket = vec![];
+    for c in pocket_fen.chars() {
+        if Piece::from_char(c).is_none() {
+            return Err(format!("Invalid FEN-string: Unknown piece {} in pocket", c));
+        }
+        pocket.push(c);
+    }
+
+    // the size of the board is however many ranks and files the FEN has
+    let mut rows: Vec<Vec<Option<Piece>>> = vec![];
+    for rank in board_fen.split('/') {
+        let mut row = vec![];
+        let mut chars = rank.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                // empty squares, wide boards can have two digits
+                '1'..='9' => {
+                    let mut count = c.to_digit(10).unwrap_or(0) as usize;
+                    while let Some(digit) = chars.peek().and_then(|d| d.to_digit(10)) {
+                        count = count
+                            .checked_mul(10)
+                            .and_then(|count| count.checked_add(digit as usize))
+                            .ok_or("Invalid FEN-string: Too many squares")?;
+                        chars.next();
                     }
+                    if count > MAX_SIZE - row.len() {
+                        return Err("Invalid FEN-string: Too many squares".to_string());
+                    }
+                    row.extend(std::iter::repeat_n(None, count));
                 }
+                // crazyhouse marks promoted pieces with a ~ after them
+                '~' => match row
+                    .last_mut()
+                    .and_then(|sq: &mut Option<Piece>| sq.as_mut())
+                {
+                    Some(piece) => piece.promoted = true,
+                    None => return Err("Invalid FEN-string: ~ without a piece".to_string()),
+                },
+                _ => match Piece::from_char(c) {
+                    Some(piece) => row.push(Some(piece)),
+                    None => return Err(format!("Invalid FEN-string: Unknown piece {}", c)),
+                },
             }
-            _ => {
-                let moves: Vec<i32>;
-                let mut color: bool = true;
-                let mut max_steps = 1;
-                match c {
-                    'p' => {
-                        moves = vec![8]; // Black pawn: 1 square forward (8), 2 squares forward (16 for the initial move)
-                        color = false; // Black
-                    }
-                    'P' => {
-                        moves = vec![-8]; // White pawn: 1 square forward (-8), 2 squares forward (-16 for the initial move)
-                        color = true; // White
-                    }
-                    'r' => {
-                        moves = vec![8, -8, 1, -1]; // Black rook: vertically (±8) or horizontally (±1)
-                        max_steps = 8; // Rook can move up to 8 squares in any direction
-                        color = false; // Black
-                    }
-                    'R' => {
-                        moves = vec![8, -8, 1, -1]; // White rook: vertically (±8) or horizontally (±1)
-                        max_steps = 8; // Rook can move up to 8 squares in any direction
-                        color = true; // White
-                    }
-                    'n' => {
-                        moves = vec![17, 15, 10, 6, -17, -15, -10, -6]; // Black knight: "L" shapes
-                        color = false; // Black
-                    }
-                    'N' => {
-                        moves = vec![17, 15, 10, 6, -17, -15, -10, -6]; // White knight: "L" shapes
-                        color = true; // White
-                    }
-                    'b' => {
-                        moves = vec![9, 7, -9, -7]; // Black bishop: diagonally (±9, ±7)
-                        max_steps = 8; // Bishop can move up to 8 squares diagonally
-                        color = false; // Black
-                    }
-                    'B' => {
-                        moves = vec![9, 7, -9, -7]; // White bishop: diagonally (±9, ±7)
-                        max_steps = 8; // Bishop can move up to 8 squares diagonally
-                        color = true; // White
-                    }
-                    'q' => {
-                        moves = vec![8, -8, 1, -1, 9, 7, -9, -7]; // Black queen: combination of rook and bishop
-                        max_steps = 8; // Queen can move up to 8 squares in any direction
-                        color = false; // Black
-                    }
-                    'Q' => {
-                        moves = vec![8, -8, 1, -1, 9, 7, -9, -7]; // White queen: combination of rook and bishop
-                        max_steps = 8; // Queen can move up to 8 squares in any direction
-                        color = true; // White
-                    }
-                    'k' => {
-                        moves = vec![8, -8, 1, -1, 9, 7, -9, -7]; // Black king: one square in any direction
-                        color = false; // Black
-                    }
-                    'K' => {
-                        moves = vec![8, -8, 1, -1, 9, 7, -9, -7]; // White king: one square in any direction
-                        color = true; // White
-                    }
-                    _ => moves = Vec::new(),
+            if row.len() > MAX_SIZE {
+                return Err("Invalid FEN-string: Too many squares".to_string());
+            }
+        }
+        rows.push(row);
+    }
+    let width = rows[0].len();
+    if rows.iter().any(|row| row.len() != width) {
+        return Err("Invalid FEN-string: Incorrect number of squares in rank".to_string());
+    }
+    if width < 4 || !(4..=MAX_SIZE).contains(&rows.len()) {
+        return Err(format!(
+            "Invalid FEN-string: Boards have 4 to {} ranks and files",
+            MAX_SIZE
+        ));
+    }
+    let board: Vec<Option<Piece>> = rows.into_iter().flatten().collect();
+    let turn = match fen_parts[1] {
+        "w" => true,
+        "b" => false,
+        other => {
+            return Err(format!(
+                "Invalid FEN-string: Unknown side to move {}",
+                other
+            ))
+        }
+    };
+
+    let mut board = Board::new(board, width, turn);
+    board.variant = variant;
+    board.checks = checks;
+    board.pocket = pocket;
+
+    //Castling rights are stored as the squares of the rooks that can still castle.
+    //Besides KQkq, which mean the outermost rook on that side (X-FEN), the file
+    //of the rook can be given as in Shredder-FEN, e.g. HAha. Rights without the
+    //rook and the king on the back rank are dropped.
+    if fen_parts[2] != "-" {
+        for c in fen_parts[2].chars() {
+            let color = c.is_ascii_uppercase();
+            let rank = board.back_row(color) * width;
+            let rook = match c.to_ascii_lowercase() {
+                'k' => board.outermost_rook(color, true),
+                'q' => board.outermost_rook(color, false),
+                file @ 'a'..='p' if ((file as u8 - b'a') as usize) < width => {
+                    Some(rank + (file as u8 - b'a') as usize)
+                }
+                _ => return Err(format!("Invalid FEN-string: Unknown castling right {}", c)),
+            };
+            if let Some(rook) = rook.filter(|&rook| board.can_castle_with(rook)) {
+                if !board.castling.contains(&rook) {
+                    board.castling.push(rook);
                 }
-                let pos = width + height * 8;
-                let piece = Piece::new(c, moves, max_steps, color);
-                board[pos] = Some(piece);
-                width += 1;
             }
         }
     }
-    let mut turn: bool;
-    if let Some(turn_part) = fen_parts[1].chars().next() {
-        turn = true;
-        match turn_part {
-            'w' => turn = true,
-            'b' => turn = false,
-            _ => {}
+    //The en passant square has to be behind a pawn that could just have moved
+    //two squares, see Board::en_passant_candidates
+    if fen_parts[3] != "-" {
+        match board.parse_square(fen_parts[3]) {
+            Some(sq) if board.en_passant_candidates().contains(&sq) => board.en_passant = Some(sq),
+            _ => return Err("Invalid FEN-string: Bad en passant square".to_string()),
+        }
+    }
+    board.halfmove_clock = fen_parts[4]
+        .parse()
+        .map_err(|_| "Invalid FEN-string: Bad halfmove clock".to_string())?;
+    board.fullmove_number = fen_parts[5]
+        .parse()
+        .map_err(|_| "Invalid FEN-string: Bad move number".to_string())?;
+
+    // the position might already be check or mate
+    board.update_state();
+    Ok(board)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Counts the move paths of the given depth, each promotion piece and drop
+    // counting as its own move
+    pub(crate) fn perft(board: &Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
         }
-    } else {
-        return Err("Invalid FEN-string: No turn part".to_string());
+        let mut nodes = 0;
+        for (from, to) in board.legal_moves() {
+            let promotions: Vec<Option<char>> = if board.is_promotion(from, to) {
+                board.promotion_pieces().iter().map(|&c| Some(c)).collect()
+            } else {
+                vec![None]
+            };
+            for promotion in promotions {
+                let mut after = board.clone();
+                after.make_move(from, to, promotion);
+                nodes += perft(&after, depth - 1);
+            }
+        }
+        for (name, to) in board.drops() {
+            let mut after = board.clone();
+            after.make_move(to, to, Some(name));
+            nodes += perft(&after, depth - 1);
+        }
+        nodes
+    }
+
+    fn perft_fen(fen: &str, depth: u32) -> u64 {
+        perft(&create_board(Some(fen)).unwrap(), depth)
     }
 
-    return Ok(Board::new(board, turn));
+    // the counts from the chess programming wiki
+    #[test]
+    fn perft_start_position() {
+        let board = create_board(None).unwrap();
+        assert_eq!(perft(&board, 1), 20);
+        assert_eq!(perft(&board, 2), 400);
+        assert_eq!(perft(&board, 3), 8902);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft_fen(fen, 1), 48);
+        assert_eq!(perft_fen(fen, 2), 2039);
+        assert_eq!(perft_fen(fen, 3), 97862);
+    }
+
+    #[test]
+    fn perft_en_passant_and_pins() {
+        assert_eq!(
+            perft_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4),
+            43238
+        );
+    }
+
+    #[test]
+    fn perft_promotions_and_castling() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft_fen(fen, 3), 9467);
+        let fen = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+        assert_eq!(perft_fen(fen, 3), 62379);
+    }
+
+    #[test]
+    fn perft_chess960() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(perft_fen(fen, 3), 12189);
+    }
+
+    #[test]
+    fn side_to_move_is_w_or_b() {
+        let fen = |turn| format!("4k3/8/8/8/8/8/8/4K3 {} - - 0 1", turn);
+        assert!(create_board(Some(&fen("w"))).unwrap().turn);
+        assert!(!create_board(Some(&fen("b"))).unwrap().turn);
+        for turn in ["x", "W", "white", "wb"] {
+            assert!(create_board(Some(&fen(turn))).is_err(), "{}", turn);
+        }
+    }
 }
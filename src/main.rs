@@ -1,92 +1,915 @@
 use glutin_window::GlutinWindow;
-use std::cmp;
 
-use opengl_graphics::{GlGraphics, GlyphCache, OpenGL, TextureSettings};
+use opengl_graphics::{GlGraphics, OpenGL};
 use piston::event_loop::{EventSettings, Events};
-use piston::input::{Button, MouseButton};
-use piston::{EventLoop, RenderEvent, WindowSettings};
-use piston::{MouseCursorEvent, PressEvent};
+use piston::input::{Button, Event, FileDrag, Input, Key, MouseButton};
+use piston::{EventLoop, RenderEvent, ResizeEvent, UpdateEvent, WindowSettings};
+use piston::{MouseCursorEvent, MouseScrollEvent, PressEvent, ReleaseEvent};
+use std::path::{Path, PathBuf};
 
-pub use crate::lib::create_board;
-pub use crate::lib::Board;
-pub use crate::lib::Piece;
+use chess::analysis::{self, Judgement};
+use chess::book::{Book, Choice};
+use chess::engine::{self, Blunder, BlunderCheck, Move};
+use chess::mate::{self, Verdict};
+use chess::puzzle::{self, Score, Trainer};
+use chess::retrograde::{self, Table, ENDGAMES};
+use chess::{
+    chess960_fen, create_variant_board, load_pieces, random_chess960, Board, DisplayOptions, Game,
+    Highlight, Variant, VARIANTS,
+};
+use editor::{Editor, EditorItem};
+use render::MenuItem;
 
-mod lib;
-mod render;
+// where `--solve` writes endgame tables and they are loaded from
+const ENDGAME_DIR: &str = "endgames";
+// the puzzle trainer's rating and the puzzles done
+const PUZZLE_SCORE: &str = "puzzles.toml";
+// how deep the engine looks for the H hint
+const HINT_DEPTH: u32 = 3;
+// and for `--analyse` without `--depth`
+const ANALYSIS_DEPTH: u32 = 3;
 
-// Screen size constants
-const SCREEN_WIDTH: usize = 600;
-const SCREEN_HEIGHT: usize = 600;
-const CELL_SIZE: usize = SCREEN_HEIGHT / 8;
+mod bughouse_window;
+mod editor;
+mod puzzle_window;
+mod render;
+mod session;
+mod theme;
+mod tui;
+mod uci;
 
 fn main() {
-    const FEN_STRING: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-    let mut theboard = create_board(Some(FEN_STRING)).unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // `--pieces` defines more pieces before any position is read
+    if let Some(file) = option_value(&args, "--pieces") {
+        if let Err(e) = load_pieces(Path::new(&file)) {
+            eprintln!("Could not load pieces from {}", e);
+            return;
+        }
+    }
+    // `--solve <endgame>` or `--solve all` writes endgame tables and exits,
+    // `--verify` checks them against the move generator as well
+    if let Some(name) = option_value(&args, "--solve") {
+        if let Err(e) = solve(&name, args.iter().any(|a| a == "--verify")) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    // `--mate <file>` solves the mate problems in an EPD file and exits,
+    // `--in <n>` gives the number of moves for problems without a dm
+    if let Some(file) = option_value(&args, "--mate") {
+        let moves = option_value(&args, "--in").and_then(|n| n.parse().ok());
+        if let Err(e) = solve_problems(Path::new(&file), moves) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    // `--uci` talks the UCI protocol on stdin and stdout instead of opening a window
+    if args.iter().any(|a| a == "--uci") {
+        if let Err(e) = uci::run() {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    // `--analyse <file>` runs the engine over a game, prints the report and
+    // saves the annotated game to `--out <file>`, `--depth` sets how deep
+    if let Some(file) = option_value(&args, "--analyse") {
+        let depth = option_value(&args, "--depth")
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(ANALYSIS_DEPTH);
+        let out = option_value(&args, "--out").map(PathBuf::from);
+        if let Err(e) = analyse_game(Path::new(&file), depth, out) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    if Path::new(ENDGAME_DIR).exists() {
+        if let Err(e) = retrograde::load_tables(Path::new(ENDGAME_DIR)) {
+            eprintln!("Could not load endgame tables from {}", e);
+        }
+    }
+    // `--fen` sets the start position, `--pgn` loads a game and is where it gets saved
+    let mut fen = option_value(&args, "--fen");
+    // `--variant` picks the rules, see chess::Variant
+    let variant = match option_value(&args, "--variant") {
+        Some(name) => match Variant::from_name(&name) {
+            Some(variant) => variant,
+            None => {
+                let names: Vec<&str> = VARIANTS.iter().map(|v| v.name()).collect();
+                eprintln!("Unknown variant {}, try one of: {}", name, names.join(", "));
+                return;
+            }
+        },
+        None => Variant::Standard,
+    };
+    // `--960` starts from a random Chess960 position, `--960 <number>` from that one
+    let chess960 = args.iter().any(|a| a == "--960");
+    let mut title = match variant {
+        Variant::Standard => "Chess".to_string(),
+        variant => variant.to_string(),
+    };
+    if chess960 {
+        let position = match option_value(&args, "--960").and_then(|n| n.parse().ok()) {
+            Some(index) => chess960_fen(index).map(|fen| (index, fen)),
+            None => Ok(random_chess960()),
+        };
+        match position {
+            Ok((index, position)) => {
+                title = format!("{} - Chess960 position {}", title, index);
+                fen = Some(position);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+    }
+    let start = match create_variant_board(variant, fen.as_deref()) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("Invalid FEN: {}", e);
+            return;
+        }
+    };
+    let pgn = option_value(&args, "--pgn").map(PathBuf::from);
+    let mut game = match &pgn {
+        Some(path) if path.exists() => match session::load(path) {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("Could not load {}", e);
+                return;
+            }
+        },
+        _ => new_game(&start, chess960),
+    };
+    let theboard = game.board().clone();
+    // `--print` writes the position to stdout and exits
+    if args.iter().any(|a| a == "--print") {
+        let options = DisplayOptions {
+            unicode: args.iter().any(|a| a == "--unicode"),
+            flipped: args.iter().any(|a| a == "--flip"),
+            ..DisplayOptions::default()
+        };
+        print!("{}", theboard.display(options));
+        return;
+    }
+    // `--tui` plays in the terminal instead of opening a window
+    if args.iter().any(|a| a == "--tui") {
+        let minutes = option_value(&args, "--time")
+            .and_then(|m| m.parse::<u64>().ok())
+            .unwrap_or(10);
+        if let Err(e) = tui::run(theboard, minutes * 60) {
+            eprintln!("Terminal error: {}", e);
+        }
+        return;
+    }
+
+    // without a position on the command line the last session carries on, `--new` skips it
+    let restore = fen.is_none()
+        && pgn.is_none()
+        && variant == Variant::Standard
+        && !args.iter().any(|a| a == "--new");
+    if restore && Path::new(session::AUTOSAVE).exists() {
+        match session::load(Path::new(session::AUTOSAVE)) {
+            Ok(saved) => game = saved,
+            Err(e) => eprintln!("Could not restore {}", e),
+        }
+    }
+    // `--book` reads a Polyglot opening book, its moves are listed under the
+    // move list and B plays one of them
+    let book = match option_value(&args, "--book").map(|file| Book::open(Path::new(&file))) {
+        Some(Ok(book)) => Some(book),
+        Some(Err(e)) => {
+            eprintln!("Could not load book {}", e);
+            return;
+        }
+        None => None,
+    };
+    let files = Files {
+        pgn: pgn.unwrap_or_else(|| PathBuf::from(session::DEFAULT_PGN)),
+        chess960,
+        start,
+    };
 
     let opengl = OpenGL::V3_2;
     // The piston window
-    let mut window: GlutinWindow =
-        WindowSettings::new("Chess", [SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32])
-            .graphics_api(opengl)
-            .exit_on_esc(true)
-            .build()
-            .unwrap();
+    let mut window: GlutinWindow = WindowSettings::new(title, render::DESIGN_SIZE)
+        .graphics_api(opengl)
+        .resizable(true)
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
     // the event and render structs from piston
     let mut event = Events::new(EventSettings::new().lazy(false));
     let mut gl = GlGraphics::new(opengl);
-    let mut mouse_position: [f64; 2] = [0.0, 0.0];
-    // import the font
-    let font = "fonts/DejaVuSansCondensed-Bold.ttf";
-    // Create a piston specific 'Glyphs' from the font
-    let mut glyphs =
-        GlyphCache::new(font, (), TextureSettings::new()).expect("Could not load font");
+    // `--theme` picks a built-in theme by name or loads a theme file, C cycles through them
+    let mut themes = theme::available();
+    let mut theme_index = 0;
+    if let Some(choice) = option_value(&args, "--theme") {
+        match themes.iter().position(|t| t.name == choice) {
+            Some(i) => theme_index = i,
+            None => match theme::Theme::load(Path::new(&choice)) {
+                Ok(t) => {
+                    themes.push(t);
+                    theme_index = themes.len() - 1;
+                }
+                Err(e) => eprintln!("Could not load theme {}", e),
+            },
+        }
+    }
+    let mut skin = theme::Skin::new(themes[theme_index].clone()).expect("Could not load theme");
+    // `--black` starts with black at the bottom, F flips the board while playing.
+    // With `--auto-flip` (toggled with A) the side to move is always at the bottom.
+    let mut view = render::View {
+        flipped: args.iter().any(|a| a == "--black"),
+        dragging: None,
+        dropping: None,
+        promoting: None,
+        mouse: [0.0, 0.0],
+        show_threats: false,
+        scroll: 0,
+        layout: render::Layout::new(render::DESIGN_SIZE),
+        message: None,
+        hint: None,
+        arrows: vec![],
+        marks: vec![],
+    };
+    // bughouse gets a window of its own with both boards. `--bots` says how many
+    // of the four seats the computer plays, `--time` sets the minutes on each clock.
+    if variant == Variant::Bughouse {
+        let bots = option_value(&args, "--bots")
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(3)
+            .min(4);
+        // the computer takes the seats from the end: black on board 2 first,
+        // then black on board 1 and white on board 2
+        let seats = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        let mut computer = [[false; 2]; 2];
+        for &(board, side) in &seats[4 - bots..] {
+            computer[board][side] = true;
+        }
+        let minutes = option_value(&args, "--time")
+            .and_then(|m| m.parse::<u64>().ok())
+            .unwrap_or(5);
+        let time = std::time::Duration::from_secs(minutes * 60);
+        bughouse_window::run(
+            &mut window,
+            &mut event,
+            &mut gl,
+            &mut skin,
+            view,
+            computer,
+            time,
+        );
+        return;
+    }
+    // `--puzzles <file>` opens the puzzle trainer with the puzzles in a CSV file
+    if let Some(file) = option_value(&args, "--puzzles") {
+        let score_file = Path::new(PUZZLE_SCORE);
+        let trainer = puzzle::read_puzzles(Path::new(&file))
+            .and_then(|puzzles| Ok((puzzles, Score::load(score_file)?)))
+            .and_then(|(puzzles, score)| Trainer::new(puzzles, score));
+        match trainer {
+            Ok(trainer) => puzzle_window::run(
+                &mut window,
+                &mut event,
+                &mut gl,
+                &mut skin,
+                view,
+                trainer,
+                score_file,
+            ),
+            Err(e) => eprintln!("Could not start the puzzles: {}", e),
+        }
+        return;
+    }
+    render::scroll_to_current(&mut view, &game);
+    let mut auto_flip = args.iter().any(|a| a == "--auto-flip");
+    // whether the dragged piece was already selected when the drag started,
+    // so a click on a selected piece still deselects it
+    let mut was_selected = false;
+    // held down for the Ctrl shortcuts
+    let mut ctrl = false;
+    // A right-drag draws an arrow and a right click marks a square, green or
+    // with Shift red, with Alt blue and with both yellow. Delete clears them.
+    let mut drawing_from: Option<usize> = None;
+    let (mut shift, mut alt) = (false, false);
+    // Space steps through the moves on its own, `--speed` sets the seconds per move
+    // and + and - change it
+    let mut autoplay = false;
+    let mut autoplay_delay = option_value(&args, "--speed")
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|&s| s > 0.0)
+        .unwrap_or(1.0);
+    let mut autoplay_timer = 0.0;
+    // the setup mode, E or the Edit button starts it from the position shown
+    let mut editor: Option<Editor> = None;
+    // H asks the engine for a move, it's shown until the position changes
+    let mut hint: Option<(String, Move)> = None;
+    // `--blunder-check` warns before a move that loses `--blunder-pawns` of
+    // material (2 by default) or allows mate, as far as a search
+    // `--blunder-depth` moves deep sees. W turns it on and off.
+    let mut check = BlunderCheck::default();
+    let pawns = option_value(&args, "--blunder-pawns").and_then(|p| p.parse::<f64>().ok());
+    if let Some(pawns) = pawns {
+        check.material = (pawns * 100.0) as i32;
+    }
+    if let Some(depth) = option_value(&args, "--blunder-depth").and_then(|d| d.parse().ok()) {
+        check.depth = depth;
+    }
+    let mut coach = Coach {
+        on: args.iter().any(|a| a == "--blunder-check"),
+        check,
+        warned: None,
+    };
 
     while let Some(e) = event.next(&mut window) {
-        if theboard.game_state == lib::GameState::GameOver {
-            println!("Game Over");
-            break;
+        if let Some(args) = e.resize_args() {
+            view.layout = render::Layout::new(args.window_size);
         }
 
         if let Some(position) = e.mouse_cursor_args() {
-            mouse_position = position;
+            view.mouse = position;
         }
 
+        // In setup mode clicks and keys go to the editor until Play or Cancel.
+        // A left click puts the palette piece down, a right click clears the square.
+        if let Some(setup) = &mut editor {
+            if let Some(button) = e.press_args() {
+                match button {
+                    Button::Mouse(MouseButton::Left) => {
+                        if let Some(pos) = render::square_at(view.mouse, &setup.board, &view) {
+                            setup.click_square(pos);
+                        } else if let Some(item) = render::editor_item_at(view.mouse, setup, &view)
+                        {
+                            match item {
+                                // the panel shows why an illegal position can't be played
+                                EditorItem::Play if setup.board.validate().is_ok() => {
+                                    game = Game::new(setup.board.clone());
+                                    editor = None;
+                                    view.scroll = 0;
+                                    autosave(&game);
+                                }
+                                EditorItem::Play => {}
+                                EditorItem::Cancel => editor = None,
+                                _ => setup.apply(item),
+                            }
+                        }
+                    }
+                    Button::Mouse(MouseButton::Right) => {
+                        if let Some(pos) = render::square_at(view.mouse, &setup.board, &view) {
+                            setup.board.place(pos, None);
+                        }
+                    }
+                    Button::Keyboard(Key::E) => editor = None,
+                    _ => {}
+                }
+                continue;
+            }
+        }
+
+        if auto_flip {
+            view.flipped = !game.board().turn;
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::LCtrl | Key::RCtrl)) => ctrl = true,
+            Some(Button::Keyboard(Key::LShift | Key::RShift)) => shift = true,
+            Some(Button::Keyboard(Key::LAlt | Key::RAlt)) => alt = true,
+            // Ctrl+N new game, Ctrl+S save, Ctrl+O load, Ctrl+E export the position as FEN
+            Some(Button::Keyboard(key)) if ctrl => {
+                let item = match key {
+                    Key::N => Some(MenuItem::New),
+                    Key::S => Some(MenuItem::Save),
+                    Key::O => Some(MenuItem::Load),
+                    Key::E => Some(MenuItem::Fen),
+                    _ => None,
+                };
+                if let Some(item) = item {
+                    run_command(item, &mut game, &mut editor, &mut view, &files);
+                }
+            }
+            Some(Button::Keyboard(Key::E)) => {
+                run_command(MenuItem::Setup, &mut game, &mut editor, &mut view, &files);
+                autoplay = false;
+            }
+            // Left and Right step through the moves, Home and End jump to the start
+            // and the end, Down enters a variation and Up goes back to where it started
+            Some(Button::Keyboard(Key::Left)) => {
+                game.go_to(game.current().saturating_sub(1));
+                autoplay = false;
+            }
+            Some(Button::Keyboard(Key::Right)) => game.go_to(game.current() + 1),
+            Some(Button::Keyboard(Key::Home)) => {
+                game.go_to(0);
+                autoplay = false;
+            }
+            Some(Button::Keyboard(Key::End)) => game.go_to(game.moves().len()),
+            Some(Button::Keyboard(Key::Down)) => {
+                game.enter_variation();
+            }
+            Some(Button::Keyboard(Key::Up)) => {
+                game.leave_variation();
+            }
+            Some(Button::Keyboard(Key::Space)) => {
+                autoplay = !autoplay;
+                autoplay_timer = 0.0;
+            }
+            Some(Button::Keyboard(Key::Equals | Key::Plus | Key::NumPadPlus)) => {
+                autoplay_delay = (autoplay_delay / 1.5).max(0.1);
+                view.message = Some(format!("Autoplay every {:.1} s", autoplay_delay));
+            }
+            Some(Button::Keyboard(Key::Minus | Key::NumPadMinus)) => {
+                autoplay_delay = (autoplay_delay * 1.5).min(30.0);
+                view.message = Some(format!("Autoplay every {:.1} s", autoplay_delay));
+            }
+            Some(Button::Keyboard(Key::F)) => {
+                view.flipped = !view.flipped;
+                auto_flip = false;
+            }
+            Some(Button::Keyboard(Key::A)) => auto_flip = !auto_flip,
+            Some(Button::Keyboard(Key::B)) => {
+                match book
+                    .as_ref()
+                    .and_then(|b| b.pick(game.board(), Choice::Weighted))
+                {
+                    Some((from, to, promotion)) => {
+                        game.play(from, to, promotion);
+                        render::scroll_to_current(&mut view, &game);
+                        autosave(&game);
+                    }
+                    None => view.message = Some("No book move".to_string()),
+                }
+            }
+            Some(Button::Keyboard(Key::H)) => {
+                let board = game.board();
+                match engine::choose_move(board, HINT_DEPTH, book.as_ref()) {
+                    Some(mv) => hint = Some((board.to_fen(), mv)),
+                    None => view.message = Some("No moves".to_string()),
+                }
+            }
+            Some(Button::Keyboard(Key::W)) => {
+                coach.on = !coach.on;
+                let state = if coach.on { "on" } else { "off" };
+                view.message = Some(format!("Blunder check {}", state));
+            }
+            Some(Button::Keyboard(Key::Delete | Key::Backspace)) => {
+                game.clear_drawings(game.node());
+                autosave(&game);
+            }
+            // overlay of the squares the opponent attacks
+            Some(Button::Keyboard(Key::T)) => view.show_threats = !view.show_threats,
+            Some(Button::Keyboard(Key::C)) => {
+                theme_index = (theme_index + 1) % themes.len();
+                match theme::Skin::new(themes[theme_index].clone()) {
+                    Ok(next) => skin = next,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            _ => {}
+        }
+        match e.release_args() {
+            Some(Button::Keyboard(Key::LCtrl | Key::RCtrl)) => ctrl = false,
+            Some(Button::Keyboard(Key::LShift | Key::RShift)) => shift = false,
+            Some(Button::Keyboard(Key::LAlt | Key::RAlt)) => alt = false,
+            _ => {}
+        }
+
+        if let Some(args) = e.update_args() {
+            if autoplay {
+                autoplay_timer += args.dt;
+                if autoplay_timer >= autoplay_delay {
+                    autoplay_timer = 0.0;
+                    game.go_to(game.current() + 1);
+                    render::scroll_to_current(&mut view, &game);
+                    // it stops at the end of the line
+                    autoplay = game.current() < game.moves().len();
+                }
+            }
+        }
+        if let Some(Button::Keyboard(_)) = e.press_args() {
+            render::scroll_to_current(&mut view, &game);
+            // any key takes a waiting promotion back
+            view.promoting = None;
+        }
+
+        // a .pgn or .fen file dropped on the window is opened
+        if let Event::Input(Input::FileDrag(FileDrag::Drop(path)), _) = &e {
+            match session::load(path) {
+                Ok(loaded) => {
+                    game = loaded;
+                    view.message = Some(format!("Loaded {}", path.display()));
+                    render::scroll_to_current(&mut view, &game);
+                    autosave(&game);
+                }
+                Err(e) => view.message = Some(e),
+            }
+        }
+
+        if let Some([_, dy]) = e.mouse_scroll_args() {
+            let rows = game.moves().len().div_ceil(2);
+            if dy > 0.0 {
+                view.scroll = view.scroll.saturating_sub(1);
+            } else if view.scroll + render::MOVE_ROWS < rows {
+                view.scroll += 1;
+            }
+        }
+
+        // Pressing on one of your own pieces picks it up, releasing it on a valid
+        // square moves it and anywhere else snaps it back. A press and release on
+        // the same square is a normal click, so click-click moves still work.
+        // the picker goes away when the position changed under it
+        view.promoting = view
+            .promoting
+            .filter(|&(from, to)| game.board().is_legal(from, to, None));
         if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
-            let x: usize = cmp::min(
-                (mouse_position[0] / CELL_SIZE as f64) as usize,
-                SCREEN_WIDTH - 1,
-            );
-            let y: usize = cmp::min(
-                (mouse_position[1] / CELL_SIZE as f64) as usize,
-                SCREEN_HEIGHT - 1,
-            );
-            let pos = x + 8 * y;
-            if let Some(sq) = theboard.selected {
-                if sq == pos {
-                    theboard.selected = None;
-                } else if let Some(_sq2) = &theboard.board[sq] {
-                    if theboard.get_valid_moves(sq).contains(&pos) {
-                        theboard.move_piece(sq, pos);
-                        theboard.selected = None;
-                    } else {
-                        theboard.selected = Some(pos);
+            if let Some((from, to)) = view.promoting {
+                // a click on a piece of the picker promotes to it, anywhere else
+                // takes the move back
+                let picked = render::promotion_at(view.mouse, game.board(), &view);
+                view.promoting = None;
+                if let Some(name) = picked {
+                    match coach.warning(game.board(), (from, to, Some(name))) {
+                        Some(warning) => view.message = Some(warning),
+                        None => {
+                            game.board_mut().selected = None;
+                            if game.play(from, to, Some(name)) {
+                                render::scroll_to_current(&mut view, &game);
+                                autosave(&game);
+                            }
+                        }
+                    }
+                }
+            } else if let Some(pos) = render::square_at(view.mouse, game.board(), &view) {
+                view.message = None;
+                let board = game.board();
+                let is_target = match board.selected {
+                    Some(sq) => {
+                        board.board[sq].is_some() && board.get_valid_moves(sq).contains(&pos)
                     }
+                    None => false,
+                };
+                let own_piece = match &board.board[pos] {
+                    Some(piece) => piece.color() == board.turn && !board.is_finished(),
+                    None => false,
+                };
+                let promotion = board
+                    .selected
+                    .filter(|&from| is_target && board.is_promotion(from, pos));
+                let warning = match board.selected {
+                    Some(from) if is_target && promotion.is_none() => {
+                        coach.warning(board, (from, pos, None))
+                    }
+                    _ => None,
+                };
+                if own_piece && !is_target {
+                    was_selected = board.selected == Some(pos);
+                    game.board_mut().selected = Some(pos);
+                    view.dragging = Some(pos);
+                } else if let Some(from) = promotion {
+                    view.promoting = Some((from, pos));
+                } else if warning.is_some() {
+                    // held back, the piece stays selected to try again
+                    view.message = warning;
+                } else if game.select_square(pos).is_some() {
+                    render::scroll_to_current(&mut view, &game);
+                    autosave(&game);
+                }
+            } else if let Some(name) = render::pocket_at(view.mouse, game.board(), &view) {
+                // crazyhouse pieces are dragged from the pocket of the side to move
+                let board = game.board();
+                if name.is_ascii_uppercase() == board.turn && !board.is_finished() {
+                    game.board_mut().selected = None;
+                    view.dropping = Some(name);
+                }
+            } else if let Some(item) = render::menu_at(view.mouse, &view) {
+                run_command(item, &mut game, &mut editor, &mut view, &files);
+                autoplay = false;
+            } else if let Some(index) = render::move_at(view.mouse, &view) {
+                // clicking a move in the list shows the position after it
+                if index < game.moves().len() {
+                    game.go_to(index + 1);
+                }
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+            if let Some(from) = view.dragging.take() {
+                match render::square_at(view.mouse, game.board(), &view) {
+                    // a click on the selected piece deselects it
+                    Some(to) if to == from && was_selected => game.board_mut().selected = None,
+                    // a pawn reaching the last rank waits for the picker
+                    Some(to)
+                        if to != from
+                            && game.board().get_valid_moves(from).contains(&to)
+                            && game.board().is_promotion(from, to) =>
+                    {
+                        view.promoting = Some((from, to))
+                    }
+                    Some(to) if to != from && game.board().get_valid_moves(from).contains(&to) => {
+                        match coach.warning(game.board(), (from, to, None)) {
+                            Some(warning) => view.message = Some(warning),
+                            None => {
+                                game.select_square(to);
+                                render::scroll_to_current(&mut view, &game);
+                                autosave(&game);
+                            }
+                        }
+                    }
+                    // illegal drop, the piece goes back and stays selected
+                    _ => {}
+                }
+            }
+            if let Some(name) = view.dropping.take() {
+                // anywhere but a square it can be dropped on puts it back in the pocket
+                if let Some(to) = render::square_at(view.mouse, game.board(), &view) {
+                    let board = game.board();
+                    let warning = board
+                        .can_drop(name, to)
+                        .then(|| coach.warning(board, (to, to, Some(name))))
+                        .flatten();
+                    if warning.is_some() {
+                        view.message = warning;
+                    } else if game.play(to, to, Some(name)) {
+                        render::scroll_to_current(&mut view, &game);
+                        autosave(&game);
+                    }
+                }
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Right)) = e.press_args() {
+            drawing_from = render::square_at(view.mouse, game.board(), &view);
+        }
+        if let Some(Button::Mouse(MouseButton::Right)) = e.release_args() {
+            // let go off the board nothing is drawn
+            let to = render::square_at(view.mouse, game.board(), &view);
+            if let (Some(from), Some(to)) = (drawing_from.take(), to) {
+                let color = highlight(shift, alt);
+                if from == to {
+                    game.toggle_mark(game.node(), color, to);
                 } else {
-                    theboard.selected = Some(pos);
+                    game.toggle_arrow(game.node(), color, from, to);
                 }
-            } else {
-                theboard.selected = Some(pos);
+                autosave(&game);
             }
         }
 
         if let Some(args) = e.render_args() {
+            // the board follows the window size, the mouse is mapped back through the same layout
+            view.layout = render::Layout::new(args.window_size);
+            // the hint and the drawings belong to the game, the setup mode has none
+            let playing = editor.is_none();
+            view.hint = hint
+                .as_ref()
+                .filter(|(fen, _)| playing && *fen == game.board().to_fen())
+                .map(|&(_, (from, to, _))| (from, to));
+            view.arrows = if playing {
+                game.arrows(game.node()).to_vec()
+            } else {
+                vec![]
+            };
+            view.marks = if playing {
+                game.marks(game.node()).to_vec()
+            } else {
+                vec![]
+            };
+            // the arrow being drawn follows the mouse
+            let under_mouse = render::square_at(view.mouse, game.board(), &view);
+            if let (Some(from), Some(to)) = (drawing_from, under_mouse) {
+                if from != to {
+                    view.arrows.push((highlight(shift, alt), from, to));
+                }
+            }
             gl.draw(args.viewport(), |c, g| {
                 // clear the screen
                 use graphics::clear;
-                clear([1.0; 4], g);
+                clear(skin.theme.background.0, g);
                 // draw board
-                render::draw_board(&c, g, theboard.clone(), &mut glyphs);
+                match &editor {
+                    Some(setup) => {
+                        render::draw_board(&c, g, setup.board.clone(), &mut skin, &view);
+                        render::draw_editor_panel(&c, g, setup, &mut skin, &view);
+                    }
+                    None => {
+                        render::draw_board(&c, g, game.board().clone(), &mut skin, &view);
+                        render::draw_panel(&c, g, &game, &mut skin, &view, book.as_ref());
+                    }
+                }
             });
         }
     }
+    autosave(&game);
+}
+
+// What the menu commands need from the command line
+struct Files {
+    // where Save writes and Load reads
+    pgn: PathBuf,
+    // the position New starts from
+    start: Board,
+    chess960: bool,
+}
+
+// The blunder check while playing. A move it warns about isn't played, but
+// trying the same move again right away plays it.
+struct Coach {
+    on: bool,
+    check: BlunderCheck,
+    // the position and move of the last warning
+    warned: Option<(String, Move)>,
+}
+
+impl Coach {
+    // What's wrong with a move, None when it can be played
+    fn warning(&mut self, board: &Board, mv: Move) -> Option<String> {
+        if !self.on {
+            return None;
+        }
+        let position = board.to_fen();
+        if self.warned.take() == Some((position.clone(), mv)) {
+            return None;
+        }
+        let san = board.san(mv.0, mv.1, mv.2);
+        let warning = match engine::find_blunder(board, mv, &self.check)? {
+            Blunder::AllowsMate(moves) => format!("{}?? allows mate in {}", san, moves),
+            Blunder::LosesMaterial(centipawns) => {
+                format!("{}?? loses {:.1} pawns", san, centipawns as f64 / 100.0)
+            }
+        };
+        self.warned = Some((position, mv));
+        Some(warning)
+    }
+}
+
+// The color of a drawing for the modifier keys held
+fn highlight(shift: bool, alt: bool) -> Highlight {
+    match (shift, alt) {
+        (false, false) => Highlight::Green,
+        (true, false) => Highlight::Red,
+        (false, true) => Highlight::Blue,
+        (true, true) => Highlight::Yellow,
+    }
+}
+
+fn new_game(start: &Board, chess960: bool) -> Game {
+    let mut game = Game::new(start.clone());
+    // other variants keep their own Variant tag, the FEN has the start position
+    if chess960 && start.variant == Variant::Standard {
+        game.set_tag("Variant", "Chess960");
+    }
+    game
+}
+
+fn run_command(
+    item: MenuItem,
+    game: &mut Game,
+    editor: &mut Option<Editor>,
+    view: &mut render::View,
+    files: &Files,
+) {
+    let pgn = files.pgn.display();
+    let result = match item {
+        MenuItem::Setup => {
+            *editor = Some(Editor::new(game.board()));
+            view.message = None;
+            return;
+        }
+        MenuItem::New => {
+            *game = new_game(&files.start, files.chess960);
+            Ok("New game".to_string())
+        }
+        MenuItem::Save => session::save_pgn(game, &files.pgn).map(|_| format!("Saved {}", pgn)),
+        MenuItem::Load => session::load(&files.pgn).map(|loaded| {
+            *game = loaded;
+            format!("Loaded {}", pgn)
+        }),
+        MenuItem::Fen => {
+            let path = Path::new(session::DEFAULT_FEN);
+            session::save_fen(game, path).map(|_| format!("Saved {}", path.display()))
+        }
+    };
+    view.message = Some(result.unwrap_or_else(|e| e));
+    view.scroll = 0;
+    render::scroll_to_current(view, game);
+    autosave(game);
+}
+
+fn autosave(game: &Game) {
+    if let Err(e) = session::save_pgn(game, Path::new(session::AUTOSAVE)) {
+        eprintln!("Autosave failed: {}", e);
+    }
+}
+
+// Solves one endgame or all of them and saves the tables in ENDGAME_DIR
+fn solve(name: &str, verify: bool) -> Result<(), String> {
+    let names: Vec<&str> = if name == "all" {
+        ENDGAMES.to_vec()
+    } else {
+        vec![name]
+    };
+    std::fs::create_dir_all(ENDGAME_DIR).map_err(|e| format!("{}: {}", ENDGAME_DIR, e))?;
+    for name in names {
+        let table = Table::solve(name)?;
+        if verify {
+            table.verify()?;
+        }
+        let path = Path::new(ENDGAME_DIR).join(format!("{}.dtm", table.name));
+        table.write(&path)?;
+        println!(
+            "{}: longest mate {} moves{}, saved {}",
+            table.name,
+            table.longest(),
+            if verify { ", verified" } else { "" },
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+// Prints the moves that lost something and how each side played, and saves
+// the annotated game next to the original as name-analysis.pgn unless `out` says where
+fn analyse_game(path: &Path, depth: u32, out: Option<PathBuf>) -> Result<(), String> {
+    let game = session::load(path)?;
+    let report = analysis::analyse(&game, depth);
+    for mv in &report.moves {
+        if mv.judgement == Judgement::Best || mv.judgement == Judgement::Good {
+            continue;
+        }
+        let board = game.board_at(game.parent(mv.node).unwrap_or(0));
+        let number = if mv.white {
+            format!("{}.", board.fullmove_number)
+        } else {
+            format!("{}...", board.fullmove_number)
+        };
+        println!(
+            "{} {}: {} ({} -> {}), {} was best",
+            number,
+            mv.san,
+            mv.judgement.name(),
+            analysis::format_score(mv.before),
+            analysis::format_score(mv.after),
+            mv.best
+        );
+    }
+    for (side, summary) in [("White", &report.white), ("Black", &report.black)] {
+        println!(
+            "{}: accuracy {:.0}%, average loss {:.0}, inaccuracies {}, mistakes {}, blunders {}",
+            side,
+            summary.accuracy,
+            summary.acpl,
+            summary.inaccuracies,
+            summary.mistakes,
+            summary.blunders
+        );
+    }
+    let out = out.unwrap_or_else(|| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{}-analysis.pgn", stem))
+    });
+    session::save_pgn(&analysis::annotate(&game, &report), &out)?;
+    println!("Saved {}", out.display());
+    Ok(())
+}
+
+// Checks every problem in an EPD file: that it has a mate in its number of
+// moves but none quicker, that the key is the only one and matches the bm
+fn solve_problems(path: &Path, moves: Option<u32>) -> Result<(), String> {
+    for (i, problem) in mate::read_problems(path)?.iter().enumerate() {
+        // a line that can't be read is reported and the rest still checked
+        let problem = match problem {
+            Ok(problem) => problem,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let name = problem
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("Problem {}", i + 1));
+        let Some(n) = problem.moves.or(moves) else {
+            println!("{}: no dm, give the number of moves with --in", name);
+            continue;
+        };
+        let (analysis, verdict) = mate::check(problem, n);
+        let verdict = match verdict {
+            Verdict::NoMate => format!("no mate in {}", n),
+            Verdict::Shorter(quicker) => format!("mate in {} already", quicker),
+            Verdict::Cooked(keys) => format!("cooked, keys {}", keys.join(", ")),
+            Verdict::WrongKey { key, bm } => format!("key {} but bm says {}", key, bm),
+            Verdict::Sound => "sound".to_string(),
+        };
+        println!("{}: mate in {}, {}", name, n, verdict);
+        for solution in &analysis.solutions {
+            print!("{}", mate::write_solution(&problem.board, solution));
+        }
+    }
+    Ok(())
+}
+
+// The argument following `name`, e.g. `--time 5`
+fn option_value(args: &[String], name: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == name)?;
+    args.get(i + 1).cloned()
 }
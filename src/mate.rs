@@ -0,0 +1,361 @@
+use std::fs;
+use std::path::Path;
+
+use crate::engine::Move;
+use crate::{create_board, Board, GameState};
+
+// How a forced mate goes: the attacker's move and, for every reply the
+// defender has, how the mate goes on from there. A move that mates has no
+// replies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub key: Move,
+    pub defenses: Vec<(Move, Solution)>,
+}
+
+impl Solution {
+    // How many moves the mate takes against the best defense
+    pub fn moves(&self) -> u32 {
+        1 + self
+            .defenses
+            .iter()
+            .map(|(_, next)| next.moves())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// Everything a mate-in-n problem needs checked: every first move that forces
+// mate, more than one being a cook, and for every other first move a defense
+// that refutes it. A try without a refutation stalemates or ends the game
+// some other way.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    pub solutions: Vec<Solution>,
+    pub tries: Vec<(Move, Option<Move>)>,
+}
+
+// A problem from an EPD file: the position, its `id`, the `dm` number of
+// moves and the `bm` key when the file gives them
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub board: Board,
+    pub id: Option<String>,
+    pub moves: Option<u32>,
+    pub key: Option<String>,
+}
+
+// What the analysis of a mate-in-n problem says about it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Sound,
+    NoMate,
+    // there's a mate in fewer moves
+    Shorter(u32),
+    // more than one key, in SAN
+    Cooked(Vec<String>),
+    // the key isn't the `bm` move of the EPD
+    WrongKey { key: String, bm: String },
+}
+
+// Solves a problem as a mate in `n` and judges it from that one search
+pub fn check(problem: &Problem, n: u32) -> (Analysis, Verdict) {
+    let board = &problem.board;
+    let analysis = solve(board, n);
+    let keys: Vec<String> = analysis
+        .solutions
+        .iter()
+        .map(|solution| {
+            let (from, to, promotion) = solution.key;
+            board.san(from, to, promotion)
+        })
+        .collect();
+    let shortest = analysis.solutions.iter().map(Solution::moves).min();
+    let verdict = match shortest {
+        None => Verdict::NoMate,
+        Some(moves) if moves < n => Verdict::Shorter(moves),
+        Some(_) if keys.len() > 1 => Verdict::Cooked(keys),
+        Some(_) => match &problem.key {
+            Some(bm)
+                if bm.trim_end_matches(['+', '#', '!']) != keys[0].trim_end_matches(['+', '#']) =>
+            {
+                Verdict::WrongKey {
+                    key: keys[0].clone(),
+                    bm: bm.clone(),
+                }
+            }
+            _ => Verdict::Sound,
+        },
+    };
+    (analysis, verdict)
+}
+
+// Tries every first move of the side to move for a mate in at most `n`
+// moves whatever the defense, and works out the solution tree of each one
+// that does
+pub fn solve(board: &Board, n: u32) -> Analysis {
+    let mut analysis = Analysis::default();
+    for mv in board.all_moves() {
+        if forces_mate(board, mv, n) {
+            analysis.solutions.push(solution(board, mv, n));
+        } else {
+            analysis.tries.push((mv, refutation(board, mv, n)));
+        }
+    }
+    analysis
+}
+
+// Whether the side to move can mate in at most `n` moves
+pub fn mates_in(board: &Board, n: u32) -> bool {
+    n > 0
+        && board
+            .all_moves()
+            .into_iter()
+            .any(|mv| forces_mate(board, mv, n))
+}
+
+// The fewest moves to mate for the side to move, up to `n`
+pub fn shortest_mate(board: &Board, n: u32) -> Option<u32> {
+    (1..=n).find(|&moves| mates_in(board, moves))
+}
+
+// Whether `mv` mates in at most `n` moves against every defense
+fn forces_mate(board: &Board, mv: Move, n: u32) -> bool {
+    let after = play(board, mv);
+    if has_won(&after, board.turn) {
+        return true;
+    }
+    if n <= 1 || after.is_finished() {
+        return false;
+    }
+    after
+        .all_moves()
+        .into_iter()
+        .all(|defense| mates_in(&play(&after, defense), n - 1))
+}
+
+// The first defense against `mv` that escapes a mate in `n`
+fn refutation(board: &Board, mv: Move, n: u32) -> Option<Move> {
+    let after = play(board, mv);
+    if n <= 1 || after.is_finished() {
+        return after.all_moves().first().copied();
+    }
+    after
+        .all_moves()
+        .into_iter()
+        .find(|&defense| !mates_in(&play(&after, defense), n - 1))
+}
+
+// The tree of a move known to force mate in `n`, following the quickest
+// mate after each defense
+fn solution(board: &Board, key: Move, n: u32) -> Solution {
+    let after = play(board, key);
+    let mut defenses = vec![];
+    if !has_won(&after, board.turn) {
+        for defense in after.all_moves() {
+            let position = play(&after, defense);
+            let next = (1..n).find_map(|moves| {
+                position
+                    .all_moves()
+                    .into_iter()
+                    .find(|&mv| forces_mate(&position, mv, moves))
+                    .map(|mv| solution(&position, mv, moves))
+            });
+            if let Some(next) = next {
+                defenses.push((defense, next));
+            }
+        }
+    }
+    Solution { key, defenses }
+}
+
+// Whether `color` has won in the position, by mate or a variant rule
+fn has_won(board: &Board, color: bool) -> bool {
+    match board.game_state {
+        GameState::Checkmate => board.turn != color,
+        GameState::Won(winner) => winner == color,
+        _ => false,
+    }
+}
+
+fn play(board: &Board, (from, to, promotion): Move) -> Board {
+    let mut after = board.clone();
+    after.make_move(from, to, promotion);
+    after
+}
+
+// The solution tree in SAN, one defense to a line and indented by how deep
+// it is, e.g.
+//   1. Qd7!
+//     1... Kf8 2. Qd8#
+//     1... Kh8 2. Qe8#
+pub fn write_solution(board: &Board, solution: &Solution) -> String {
+    let (from, to, promotion) = solution.key;
+    // the key of a longer problem is marked with a !
+    let mark = if solution.defenses.is_empty() {
+        ""
+    } else {
+        "!"
+    };
+    let mut text = format!(
+        "{}{}{}\n",
+        move_number(board),
+        board.san(from, to, promotion),
+        mark
+    );
+    write_defenses(&play(board, solution.key), solution, 1, &mut text);
+    text
+}
+
+fn write_defenses(board: &Board, solution: &Solution, depth: usize, text: &mut String) {
+    for ((from, to, promotion), next) in &solution.defenses {
+        let after = play(board, (*from, *to, *promotion));
+        let (key_from, key_to, key_promotion) = next.key;
+        text.push_str(&format!(
+            "{}{}{} {}{}\n",
+            "  ".repeat(depth),
+            move_number(board),
+            board.san(*from, *to, *promotion),
+            move_number(&after),
+            after.san(key_from, key_to, key_promotion)
+        ));
+        write_defenses(&play(&after, next.key), next, depth + 1, text);
+    }
+}
+
+// "12. " before a white move, "12... " before a black one
+fn move_number(board: &Board) -> String {
+    if board.turn {
+        format!("{}. ", board.fullmove_number)
+    } else {
+        format!("{}... ", board.fullmove_number)
+    }
+}
+
+// Reads an EPD file, one problem to a line: the first four FEN fields and
+// then operations separated by semicolons, e.g.
+// `6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - dm 1; id "back rank";`
+// A line that can't be read is an error with its line number in the list,
+// so the other problems of the file can still be checked.
+pub fn read_problems(path: &Path) -> Result<Vec<Result<Problem, String>>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            parse_epd(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))
+        })
+        .collect())
+}
+
+pub fn parse_epd(line: &str) -> Result<Problem, String> {
+    // the fields can be apart by any whitespace, the operations are the rest
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while fields.len() < 4 && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    if fields.len() < 4 {
+        return Err("An EPD line needs pieces, side, castling and en passant".to_string());
+    }
+    let board = create_board(Some(&format!("{} 0 1", fields.join(" "))))?;
+    let mut problem = Problem {
+        board,
+        id: None,
+        moves: None,
+        key: None,
+    };
+    for operation in rest.split(';') {
+        let (opcode, operand) = operation.trim().split_once(' ').unwrap_or((operation, ""));
+        let operand = operand.trim().trim_matches('"');
+        match opcode.trim() {
+            "id" => problem.id = Some(operand.to_string()),
+            "dm" => {
+                let moves = operand.parse().map_err(|_| format!("Bad dm {}", operand))?;
+                problem.moves = Some(moves);
+            }
+            "bm" => problem.key = Some(operand.to_string()),
+            _ => {}
+        }
+    }
+    Ok(problem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_epd(line: &str) -> Verdict {
+        let problem = parse_epd(line).unwrap();
+        check(&problem, problem.moves.unwrap()).1
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let line = r#"6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - dm 1; bm Rd8#; id "back rank";"#;
+        let problem = parse_epd(line).unwrap();
+        assert_eq!(problem.id.as_deref(), Some("back rank"));
+        let (analysis, verdict) = check(&problem, 1);
+        assert_eq!(verdict, Verdict::Sound);
+        assert_eq!(analysis.solutions.len(), 1);
+        assert_eq!(analysis.solutions[0].moves(), 1);
+        assert_eq!(
+            write_solution(&problem.board, &analysis.solutions[0]),
+            "1. Rd8#\n"
+        );
+    }
+
+    #[test]
+    fn mate_in_two() {
+        let line = "k7/8/2K5/8/8/8/8/1R6 w - - dm 2; bm Kc7;";
+        assert_eq!(check_epd(line), Verdict::Sound);
+        let problem = parse_epd(line).unwrap();
+        let (analysis, _) = check(&problem, 2);
+        assert_eq!(
+            write_solution(&problem.board, &analysis.solutions[0]),
+            "1. Kc7!\n  1... Ka7 2. Ra1#\n"
+        );
+        // a wrong bm, and the same position asked as a mate in 3
+        let wrong = "k7/8/2K5/8/8/8/8/1R6 w - - dm 2; bm Kb6;";
+        assert!(matches!(check_epd(wrong), Verdict::WrongKey { .. }));
+        let longer = "k7/8/2K5/8/8/8/8/1R6 w - - dm 3;";
+        assert_eq!(check_epd(longer), Verdict::Shorter(2));
+    }
+
+    #[test]
+    fn cooked() {
+        let line = "6k1/5ppp/8/8/8/8/5PPP/R2R2K1 w - - dm 1;";
+        assert_eq!(
+            check_epd(line),
+            Verdict::Cooked(vec!["Ra8#".to_string(), "Rd8#".to_string()])
+        );
+        assert_eq!(check_epd("8/8/8/8/8/8/8/K6k w - - dm 1;"), Verdict::NoMate);
+    }
+
+    #[test]
+    fn epd_fields_apart_by_any_whitespace() {
+        let problem = parse_epd("  6k1/5ppp/8/8/8/8/5PPP/3R2K1   w \t -  -   dm 1;").unwrap();
+        assert_eq!(problem.moves, Some(1));
+        assert!(problem.board.turn);
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w -").is_err());
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let path = std::env::temp_dir().join(format!("chess-mate-{}.epd", std::process::id()));
+        let text = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - dm 1;\n\
+                    # a comment\n\
+                    not a position\n\
+                    k7/8/2K5/8/8/8/8/1R6 w - - dm 2;\n";
+        fs::write(&path, text).unwrap();
+        let problems = read_problems(&path);
+        fs::remove_file(&path).unwrap();
+        let problems = problems.unwrap();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].is_ok() && problems[2].is_ok());
+        assert!(problems[1].as_ref().unwrap_err().contains("line 3"));
+    }
+}
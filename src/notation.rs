@@ -0,0 +1,241 @@
+use crate::{piece_def, Board, GameState, Variant};
+
+impl Board {
+    // Standard algebraic notation for a legal move in this position, e.g. "Nbd7", "exd5+"
+    // or "e8=Q#". `promotion` is the piece letter for pawns reaching the last rank.
+    pub fn san(&self, from: usize, to: usize, promotion: Option<char>) -> String {
+        // crazyhouse drops, e.g. "N@f3"
+        if let (true, Some(name)) = (from == to, promotion) {
+            let mut san = format!("{}@{}", name.to_ascii_uppercase(), self.square_name(to));
+            self.push_check_mark(&mut san, from, to, promotion);
+            return san;
+        }
+        let piece = match &self.board[from] {
+            Some(piece) => piece,
+            None => return String::new(),
+        };
+        let kind = piece.name.to_ascii_uppercase();
+        let en_passant =
+            kind == 'P' && self.file(from) != self.file(to) && self.board[to].is_none();
+        let capture = self.board[to].is_some() || en_passant;
+        let mut san = String::new();
+
+        if let Some(rook) = self.castling_rook(from, to) {
+            san.push_str(if rook > from { "O-O" } else { "O-O-O" });
+        } else if kind == 'P' {
+            if capture {
+                san.push(self.square_name(from).chars().next().unwrap());
+                san.push('x');
+            }
+            san.push_str(&self.square_name(to));
+            if self.is_promotion(from, to) {
+                san.push('=');
+                san.push(promotion.unwrap_or('q').to_ascii_uppercase());
+            }
+        } else {
+            san.push(kind);
+            // other pieces of the same kind that could also go to `to`
+            let others: Vec<usize> = (0..self.size())
+                .filter(|&pos| pos != from)
+                .filter(|&pos| match &self.board[pos] {
+                    Some(other) => other.name == piece.name,
+                    None => false,
+                })
+                .filter(|&pos| self.get_valid_moves(pos).contains(&to))
+                .collect();
+            if !others.is_empty() {
+                let name = self.square_name(from);
+                let same_file = others.iter().any(|&pos| self.file(pos) == self.file(from));
+                let same_rank = others.iter().any(|&pos| self.row(pos) == self.row(from));
+                if !same_file {
+                    san.push_str(&name[..1]);
+                } else if !same_rank {
+                    san.push_str(&name[1..]);
+                } else {
+                    san.push_str(&name);
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&self.square_name(to));
+        }
+
+        self.push_check_mark(&mut san, from, to, promotion);
+        san
+    }
+
+    // Adds + or # for the position after the move
+    fn push_check_mark(&self, san: &mut String, from: usize, to: usize, promotion: Option<char>) {
+        let mut after = self.clone();
+        after.make_move(from, to, promotion);
+        // a variant can end the game with a check, e.g. the third one in three-check
+        if after.game_state == GameState::Checkmate {
+            san.push('#');
+        } else if after.in_check(after.turn) {
+            san.push('+');
+        }
+    }
+
+    // Finds the legal move written as `san`. Check marks and annotations like "!?"
+    // are optional, and castling can be written with zeros as well.
+    pub fn parse_san(&self, san: &str) -> Result<(usize, usize, Option<char>), String> {
+        let clean = |s: &str| -> String {
+            s.trim_end_matches(['+', '#', '!', '?'])
+                .replace('0', "O")
+                .replace("e.p.", "")
+        };
+        let wanted = clean(san.trim());
+        for (from, to) in self.legal_moves() {
+            let promotions: Vec<Option<char>> = if self.is_promotion(from, to) {
+                self.promotion_pieces().iter().map(|&c| Some(c)).collect()
+            } else {
+                vec![None]
+            };
+            for promotion in promotions {
+                let written = clean(&self.san(from, to, promotion));
+                // "exd6" is accepted for a pawn capture, and "e8Q" without the "="
+                if written == wanted || written.replace('=', "") == wanted {
+                    return Ok((from, to, promotion));
+                }
+            }
+        }
+        for (name, to) in self.drops() {
+            let written = clean(&self.san(to, to, Some(name)));
+            // pawn drops can leave out the P, "@e4"
+            if written == wanted || written.strip_prefix('P') == Some(wanted.as_str()) {
+                return Ok((to, to, Some(name.to_ascii_lowercase())));
+            }
+        }
+        Err(format!("Illegal move {}", san))
+    }
+
+    // Finds the legal move written in UCI coordinates, e.g. "e2e4" or "e7e8q".
+    // Ranks past 9 take two digits, as in "a9a10".
+    pub fn parse_uci(&self, uci: &str) -> Result<(usize, usize, Option<char>), String> {
+        let error = || format!("Illegal move {}", uci);
+        if uci.len() < 4 || !uci.is_ascii() {
+            return Err(error());
+        }
+        // drops are written like "N@f3"
+        if let Some((name, square)) = uci.split_once('@') {
+            let to = self.parse_square(square).ok_or_else(error)?;
+            let name = name.chars().next().ok_or_else(error)?.to_ascii_lowercase();
+            if !self.can_drop(name, to) {
+                return Err(error());
+            }
+            return Ok((to, to, Some(name)));
+        }
+        let (from, rest) = self.read_square(uci).ok_or_else(error)?;
+        let (mut to, rest) = self.read_square(rest).ok_or_else(error)?;
+        let promotion = rest.chars().next();
+        let moves = self.get_valid_moves(from);
+        if !moves.contains(&to) {
+            // castling can be written as the king taking its rook (UCI_Chess960) or
+            // as the king's move, whichever way this board doesn't use
+            let other = self.castling_rook(from, to).and_then(|rook| {
+                [rook, self.castling_targets(from, rook).0]
+                    .into_iter()
+                    .find(|sq| moves.contains(sq))
+            });
+            to = other.ok_or_else(error)?;
+        }
+        Ok((from, to, promotion))
+    }
+
+    // The UCI coordinates of a move, e.g. "e2e4" or "e7e8q"
+    pub fn uci(&self, from: usize, to: usize, promotion: Option<char>) -> String {
+        if let (true, Some(name)) = (from == to, promotion) {
+            return format!("{}@{}", name.to_ascii_uppercase(), self.square_name(to));
+        }
+        let mut uci = format!("{}{}", self.square_name(from), self.square_name(to));
+        if self.is_promotion(from, to) {
+            uci.push(promotion.unwrap_or('q').to_ascii_lowercase());
+        }
+        uci
+    }
+
+    // The position as a FEN string, the counterpart of create_board
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in 0..self.height {
+            let mut empty = 0;
+            for file in 0..self.width {
+                match &self.board[rank * self.width + file] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.name);
+                        if piece.promoted() && self.variant.has_drops() {
+                            fen.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank + 1 < self.height {
+                fen.push('/');
+            }
+        }
+
+        if self.variant.has_drops() {
+            let pocket: String = [true, false]
+                .iter()
+                .flat_map(|&color| self.pocket(color))
+                .flat_map(|(name, count)| std::iter::repeat_n(name, count))
+                .collect();
+            fen.push_str(&format!("[{}]", pocket));
+        }
+        fen.push_str(if self.turn { " w " } else { " b " });
+        // KQkq for the outermost rooks, the file of the rook otherwise (Shredder-FEN)
+        let mut rooks = self.castling.clone();
+        let white_row = self.back_row(true);
+        rooks.sort_by_key(|&rook| (self.row(rook) != white_row, std::cmp::Reverse(rook)));
+        let mut castling = String::new();
+        for rook in rooks {
+            let color = self.row(rook) == white_row;
+            let king_side = self.king_square(color).is_some_and(|king| rook > king);
+            let c = if self.outermost_rook(color, king_side) == Some(rook) {
+                if king_side {
+                    'k'
+                } else {
+                    'q'
+                }
+            } else {
+                (b'a' + self.file(rook) as u8) as char
+            };
+            castling.push(if color { c.to_ascii_uppercase() } else { c });
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+        match self.en_passant {
+            Some(sq) => fen.push_str(&format!(" {}", self.square_name(sq))),
+            None => fen.push_str(" -"),
+        }
+        if self.variant == Variant::ThreeCheck {
+            let [white, black] = self.checks;
+            fen.push_str(&format!(
+                " {}+{}",
+                3u32.saturating_sub(white),
+                3u32.saturating_sub(black)
+            ));
+        }
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+}
+
+// Point values from the piece definitions, kings count as nothing
+pub fn piece_value(name: char) -> i32 {
+    piece_def(name).map_or(0, |def| def.value)
+}
@@ -0,0 +1,414 @@
+use crate::{create_variant_board, Board, Game, Highlight, Variant};
+
+// movetext lines are wrapped at this width, as the PGN standard asks
+const LINE_WIDTH: usize = 80;
+
+impl Game {
+    // The whole game as PGN, with the seven standard tags first
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        let result = self.result();
+        for name in ["Event", "Site", "Date", "Round", "White", "Black"] {
+            let value = self.tag(name).unwrap_or("?");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+        pgn.push_str(&format!("[Result \"{}\"]\n", result));
+        let start = self.start().to_fen();
+        if start != self.start().variant.start_fen() {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{}\"]\n", start));
+        }
+        for (name, value) in &self.tags {
+            let standard = [
+                "Event", "Site", "Date", "Round", "White", "Black", "Result", "SetUp", "FEN",
+            ];
+            if !standard.contains(&name.as_str()) {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        let numbered = self.write_annotations(0, &mut tokens);
+        self.write_line(0, numbered, &mut tokens);
+        tokens.push(result);
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    // Movetext tokens for the moves after `node`, with variations in parentheses.
+    // Black's moves only get a number at the start of a line or after a variation.
+    fn write_line(&self, node: usize, numbered: bool, tokens: &mut Vec<String>) {
+        let mut node = node;
+        let mut numbered = numbered;
+        while let Some(&main) = self.children(node).first() {
+            let board = self.board_at(node);
+            tokens.push(move_token(
+                board,
+                &self.move_at(main).unwrap().san,
+                numbered,
+            ));
+            numbered = self.write_annotations(main, tokens);
+            for &variation in &self.children(node)[1..] {
+                let first = move_token(board, &self.move_at(variation).unwrap().san, true);
+                tokens.push(format!("({}", first));
+                let after_comment = self.write_annotations(variation, tokens);
+                self.write_line(variation, after_comment, tokens);
+                tokens.last_mut().unwrap().push(')');
+                numbered = true;
+            }
+            node = main;
+        }
+    }
+
+    // The NAGs and the comment of a node's move, with the arrows and marked
+    // squares as [%cal Ge2e4] and [%csl Rd5] at its start. Returns whether
+    // there was a comment, the move after one needs its number again.
+    fn write_annotations(&self, node: usize, tokens: &mut Vec<String>) -> bool {
+        for nag in self.nags(node) {
+            tokens.push(format!("${}", nag));
+        }
+        let board = self.board_at(node);
+        let marks: Vec<String> = self
+            .marks(node)
+            .iter()
+            .map(|&(color, square)| format!("{}{}", color.letter(), board.square_name(square)))
+            .collect();
+        let arrows: Vec<String> = self
+            .arrows(node)
+            .iter()
+            .map(|&(color, from, to)| {
+                let (from, to) = (board.square_name(from), board.square_name(to));
+                format!("{}{}{}", color.letter(), from, to)
+            })
+            .collect();
+        let mut parts = vec![];
+        if !marks.is_empty() {
+            parts.push(format!("[%csl {}]", marks.join(",")));
+        }
+        if !arrows.is_empty() {
+            parts.push(format!("[%cal {}]", arrows.join(",")));
+        }
+        if !self.comment(node).is_empty() {
+            parts.push(self.comment(node).to_string());
+        }
+        let comment = parts.join(" ");
+        if comment.is_empty() {
+            return false;
+        }
+        // a comment can't hold its closing brace
+        tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+        true
+    }
+
+    // Reads the first game of a PGN text, with its comments and NAGs.
+    // Annotations written onto a move like "e4!?" become NAGs too.
+    pub fn from_pgn(text: &str) -> Result<Game, String> {
+        let mut tags = vec![];
+        let mut movetext = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            // a comment can go over several lines, and one of them can look like a tag
+            let in_comment = movetext.matches('{').count() > movetext.matches('}').count();
+            if line.starts_with('[') && line.ends_with(']') && !in_comment {
+                // the tags of a second game end the first one
+                if !movetext.trim().is_empty() {
+                    break;
+                }
+                tags.push(parse_tag(line)?);
+            } else if !line.starts_with('%') {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let fen = tags
+            .iter()
+            .find(|(name, _)| name == "FEN")
+            .map(|(_, value)| value.clone());
+        let variant = match tags.iter().find(|(name, _)| name == "Variant") {
+            Some((_, name)) => {
+                Variant::from_name(name).ok_or_else(|| format!("Unknown variant {}", name))?
+            }
+            None => Variant::Standard,
+        };
+        let board = create_variant_board(variant, fen.as_deref())?;
+        let mut game = Game::new(board);
+        game.tags = tags;
+
+        // where to continue after each open variation
+        let mut stack = vec![];
+        for token in tokenize(&movetext)? {
+            match token.as_str() {
+                "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                    game.set_tag("Result", &token);
+                    break;
+                }
+                // a variation replaces the move just played
+                "(" => {
+                    let parent = game
+                        .parent(game.node())
+                        .ok_or_else(|| "Variation before the first move".to_string())?;
+                    stack.push(game.node());
+                    game.go_to_node(parent);
+                }
+                ")" => {
+                    let node = stack
+                        .pop()
+                        .ok_or_else(|| "Unbalanced variation".to_string())?;
+                    game.go_to_node(node);
+                }
+                _ if token.starts_with('{') => {
+                    let node = game.node();
+                    let text = read_drawings(&mut game, node, &token[1..token.len() - 1]);
+                    let words: Vec<&str> = text.split_whitespace().collect();
+                    let text = words.join(" ");
+                    let comment = match game.comment(node) {
+                        "" => text,
+                        before => format!("{} {}", before, text),
+                    };
+                    game.set_comment(node, &comment);
+                }
+                _ if token.starts_with('$') => {
+                    let nag = token[1..]
+                        .parse()
+                        .map_err(|_| format!("Invalid NAG {}", token))?;
+                    game.add_nag(game.node(), nag);
+                }
+                _ => {
+                    let (from, to, promotion) = game.board().parse_san(&token)?;
+                    game.play(from, to, promotion);
+                    let suffix = token.trim_start_matches(|c| c != '!' && c != '?');
+                    if let Some(nag) = suffix_nag(suffix) {
+                        game.add_nag(game.node(), nag);
+                    }
+                }
+            }
+        }
+        if !stack.is_empty() {
+            return Err("Unterminated variation".to_string());
+        }
+        // show the end of the main line
+        game.go_to_node(0);
+        game.go_to(game.moves().len());
+        Ok(game)
+    }
+}
+
+// A move with its number, "12. Nf3" for white and "12... Nf6" for black
+fn move_token(board: &Board, san: &str, numbered: bool) -> String {
+    if board.turn {
+        format!("{}. {}", board.fullmove_number, san)
+    } else if numbered {
+        format!("{}... {}", board.fullmove_number, san)
+    } else {
+        san.to_string()
+    }
+}
+
+// Takes the [%cal ...] arrows and [%csl ...] marked squares out of a
+// comment and draws them on the node, returns the rest of the comment.
+// Entries that don't fit the board are left out.
+fn read_drawings(game: &mut Game, node: usize, comment: &str) -> String {
+    let mut rest = comment.to_string();
+    for command in ["[%cal ", "[%csl "] {
+        while let Some(start) = rest.find(command) {
+            let Some(length) = rest[start..].find(']') else {
+                break;
+            };
+            let entries = rest[start + command.len()..start + length].to_string();
+            rest.replace_range(start..start + length + 1, "");
+            for entry in entries.split(',').map(str::trim) {
+                let board = game.board_at(node);
+                let mut chars = entry.chars();
+                let Some(color) = chars.next().and_then(Highlight::from_letter) else {
+                    continue;
+                };
+                match board.read_square(chars.as_str()) {
+                    Some((square, "")) if command == "[%csl " => {
+                        game.toggle_mark(node, color, square)
+                    }
+                    Some((from, to)) if command == "[%cal " => {
+                        if let Some(to) = board.parse_square(to) {
+                            game.toggle_arrow(node, color, from, to);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    rest
+}
+
+// The NAG for an annotation written onto a move
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+// The move, parenthesis, comment, NAG and result tokens of the movetext,
+// without move numbers. Comments keep their braces.
+fn tokenize(movetext: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = movetext.chars().peekable();
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        let separator = c.is_whitespace() || "{;()".contains(c);
+        if separator && !word.is_empty() {
+            tokens.push(word.clone());
+            word.clear();
+        }
+        match c {
+            '{' => {
+                // comments don't nest, they go on to the closing brace
+                let mut comment = String::from("{");
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err("Unterminated comment".to_string()),
+                    }
+                }
+                comment.push('}');
+                tokens.push(comment);
+            }
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' | ')' => tokens.push(c.to_string()),
+            _ if c.is_whitespace() => {}
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    // drop move numbers ("12." or "12...", possibly stuck to the move)
+    Ok(tokens
+        .into_iter()
+        .map(|token| {
+            if token.starts_with(['{', '$'])
+                || token.contains('/')
+                || token.contains('-') && token.starts_with(['0', '1'])
+            {
+                return token;
+            }
+            token
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches('.')
+                .to_string()
+        })
+        .filter(|token| !token.is_empty())
+        .collect())
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), String> {
+    let inner = &line[1..line.len() - 1];
+    let (name, value) = inner
+        .split_once(' ')
+        .ok_or_else(|| format!("Invalid tag {}", line))?;
+    let value = value.trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(format!("Invalid tag {}", line));
+    }
+    let value = value[1..value.len() - 1]
+        .replace("\\\"", "\"")
+        .replace("\\\\", "\\");
+    Ok((name.to_string(), value))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writing a read game and reading it back gives the same PGN again
+    fn round_trip(pgn: &str) -> Game {
+        let game = Game::from_pgn(pgn).unwrap();
+        let written = game.to_pgn();
+        let again = Game::from_pgn(&written).unwrap();
+        assert_eq!(again.to_pgn(), written);
+        again
+    }
+
+    #[test]
+    fn variations_nags_and_drawings() {
+        let game = round_trip(
+            "1. e4 $1 {Best by test} (1. d4 d5 $2 (1... Nf6)) 1... e5 \
+             {[%csl Yd5] [%cal Ge2e4,Rd7d5] Open game} 2. Nf3 *",
+        );
+        let line = game.main_line(0);
+        assert_eq!(line.len(), 3);
+        assert_eq!(game.move_at(line[0]).unwrap().san, "e4");
+        assert_eq!(game.nags(line[0]), [1]);
+        assert_eq!(game.comment(line[0]), "Best by test");
+
+        // 1. d4 is the variation, with black's own alternative in it
+        let d4 = game.children(0)[1];
+        assert_eq!(game.move_at(d4).unwrap().san, "d4");
+        let d5 = game.children(d4)[0];
+        assert_eq!(game.nags(d5), [2]);
+        assert_eq!(game.move_at(game.children(d4)[1]).unwrap().san, "Nf6");
+
+        let e5 = line[1];
+        let board = game.board_at(e5);
+        let square = |name| board.parse_square(name).unwrap();
+        assert_eq!(game.marks(e5), [(Highlight::Yellow, square("d5"))]);
+        assert_eq!(
+            game.arrows(e5),
+            [
+                (Highlight::Green, square("e2"), square("e4")),
+                (Highlight::Red, square("d7"), square("d5"))
+            ]
+        );
+        assert_eq!(game.comment(e5), "Open game");
+    }
+
+    #[test]
+    fn setup_with_underpromotion() {
+        let fen = "8/1P6/8/8/8/8/6k1/K7 w - - 0 1";
+        let pgn = format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. b8=N Kf3 2. Nc6 *", fen);
+        let game = round_trip(&pgn);
+        assert_eq!(game.start().to_fen(), fen);
+        assert!(game
+            .to_pgn()
+            .contains("[FEN \"8/1P6/8/8/8/8/6k1/K7 w - - 0 1\"]"));
+        let line = game.main_line(0);
+        assert_eq!(game.move_at(line[0]).unwrap().san, "b8=N");
+        assert_eq!(game.move_at(line[0]).unwrap().promotion, Some('n'));
+        assert_eq!(
+            game.board_at(line[2]).to_fen(),
+            "8/8/2N5/8/8/5k2/8/K7 b - - 2 2"
+        );
+    }
+}
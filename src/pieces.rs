@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+
+use serde::Deserialize;
+
+// Whether a movement can go to empty squares, capture, or both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveMode {
+    Both,
+    MoveOnly,
+    CaptureOnly,
+}
+
+// One direction a piece moves in. `step` is (files, ranks) as seen by the
+// piece's owner, so positive ranks are forward for both colors. `range` is how
+// often the step can be repeated: 1 for leapers, 0 for riders that go on
+// until they run into something.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Movement {
+    pub step: (i32, i32),
+    pub range: usize,
+    pub mode: MoveMode,
+}
+
+// A kind of piece, the same for both colors
+#[derive(Clone, Debug)]
+pub struct PieceDef {
+    // the uppercase FEN letter, lowercase is the black piece
+    pub letter: char,
+    pub name: String,
+    pub movements: Vec<Movement>,
+    // rough worth in pawns, for material counts
+    pub value: i32,
+}
+
+impl PieceDef {
+    pub fn new(letter: char, name: &str, betza: &str, value: i32) -> Result<PieceDef, String> {
+        Ok(PieceDef {
+            letter: letter.to_ascii_uppercase(),
+            name: name.to_string(),
+            movements: parse_betza(betza)?,
+            value,
+        })
+    }
+}
+
+// A piece definition as written in a pieces file
+#[derive(Deserialize)]
+struct PieceConfig {
+    letter: char,
+    name: String,
+    betza: String,
+    #[serde(default)]
+    value: i32,
+}
+
+#[derive(Deserialize)]
+struct PiecesFile {
+    piece: Vec<PieceConfig>,
+}
+
+// The pieces FEN letters are looked up in. Pawns and kings keep their special
+// rules (double steps, en passant, promotion, castling, check) whatever they
+// are defined as.
+static PIECES: LazyLock<RwLock<Vec<PieceDef>>> = LazyLock::new(|| RwLock::new(builtin()));
+
+fn builtin() -> Vec<PieceDef> {
+    [
+        ('P', "Pawn", "mfWcfF", 1),
+        ('N', "Knight", "N", 3),
+        ('B', "Bishop", "B", 3),
+        ('R', "Rook", "R", 5),
+        ('Q', "Queen", "Q", 9),
+        ('K', "King", "K", 0),
+        ('A', "Archbishop", "BN", 7),
+        ('C', "Chancellor", "RN", 8),
+        ('M', "Amazon", "QN", 12),
+        ('L', "Camel", "C", 3),
+        ('Z', "Zebra", "Z", 2),
+    ]
+    .into_iter()
+    .map(|(letter, name, betza, value)| PieceDef::new(letter, name, betza, value).unwrap())
+    .collect()
+}
+
+// The definition for a FEN letter of either color
+pub fn piece_def(letter: char) -> Option<PieceDef> {
+    let letter = letter.to_ascii_uppercase();
+    let pieces = PIECES.read().unwrap();
+    pieces.iter().find(|def| def.letter == letter).cloned()
+}
+
+// The letters of every piece defined, the usual six first
+pub fn piece_letters() -> Vec<char> {
+    PIECES
+        .read()
+        .unwrap()
+        .iter()
+        .map(|def| def.letter)
+        .collect()
+}
+
+// Adds a piece, or replaces the one with the same letter
+pub fn define_piece(def: PieceDef) {
+    let mut pieces = PIECES.write().unwrap();
+    match pieces.iter_mut().find(|old| old.letter == def.letter) {
+        Some(old) => *old = def,
+        None => pieces.push(def),
+    }
+}
+
+// Defines every piece in a TOML file of [[piece]] tables with a letter,
+// name, Betza notation and value. Returns the letters defined.
+pub fn load_pieces(path: &Path) -> Result<Vec<char>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: PiecesFile =
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut letters = vec![];
+    for config in file.piece {
+        let def = PieceDef::new(config.letter, &config.name, &config.betza, config.value)
+            .map_err(|e| format!("{}: {}: {}", path.display(), config.name, e))?;
+        letters.push(def.letter);
+        define_piece(def);
+    }
+    Ok(letters)
+}
+
+// Movements from Betza's funny notation. Atoms are single steps that are
+// repeated in every direction: W (1,0), F (1,1), D (2,0), N (2,1), A (2,2),
+// H (3,0), C (3,1), Z (3,2) and G (3,3), and K, R, B, Q are short for WF,
+// WW, FF and WWFF. A doubled atom is a rider, a number after it limits the
+// range (0 for no limit). Lowercase prefixes narrow an atom down:
+// m moves only, c captures only, f b l r v s keep forward, backward, left,
+// right, vertical and sideways directions. An f or b right before l, r, v or
+// s keeps the steps going both ways, so flF is only the forward-left step,
+// and separate directions add up, fbW steps forward and backward.
+pub fn parse_betza(betza: &str) -> Result<Vec<Movement>, String> {
+    let chars: Vec<char> = betza.chars().collect();
+    let mut movements = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let mut modifiers = String::new();
+        while i < chars.len() && chars[i].is_ascii_lowercase() {
+            modifiers.push(chars[i]);
+            i += 1;
+        }
+        let letter = *chars
+            .get(i)
+            .ok_or_else(|| format!("Missing atom after {} in {}", modifiers, betza))?;
+        i += 1;
+        // the atoms a letter stands for, and whether they ride
+        let (atoms, mut range) = match letter {
+            'K' => ("WF".to_string(), 1),
+            'R' => ("W".to_string(), 0),
+            'B' => ("F".to_string(), 0),
+            'Q' => ("WF".to_string(), 0),
+            _ if chars.get(i) == Some(&letter) => {
+                i += 1;
+                (letter.to_string(), 0)
+            }
+            _ => (letter.to_string(), 1),
+        };
+        let mut digits = String::new();
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            digits.push(chars[i]);
+            i += 1;
+        }
+        if !digits.is_empty() {
+            range = digits
+                .parse()
+                .map_err(|_| format!("Bad range in {}", betza))?;
+        }
+        let mode = match (modifiers.contains('m'), modifiers.contains('c')) {
+            (true, false) => MoveMode::MoveOnly,
+            (false, true) => MoveMode::CaptureOnly,
+            _ => MoveMode::Both,
+        };
+        let directions = direction_groups(&modifiers);
+
+        for atom in atoms.chars() {
+            let (a, b) = match atom {
+                'W' => (1, 0),
+                'F' => (1, 1),
+                'D' => (2, 0),
+                'N' => (2, 1),
+                'A' => (2, 2),
+                'H' => (3, 0),
+                'C' => (3, 1),
+                'Z' => (3, 2),
+                'G' => (3, 3),
+                _ => return Err(format!("Unknown atom {} in {}", atom, betza)),
+            };
+            for (x, y) in [(a, b), (b, a)] {
+                for (sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                    let movement = Movement {
+                        step: (x * sx, y * sy),
+                        range,
+                        mode,
+                    };
+                    if keep(movement.step, &directions) && !movements.contains(&movement) {
+                        movements.push(movement);
+                    }
+                }
+            }
+        }
+    }
+    Ok(movements)
+}
+
+// The direction letters of the modifiers, an f or b with the l, r, v or s
+// after it in one group
+fn direction_groups(modifiers: &str) -> Vec<Vec<char>> {
+    let letters: Vec<char> = modifiers
+        .chars()
+        .filter(|c| "fblrvs".contains(*c))
+        .collect();
+    let mut groups = vec![];
+    let mut i = 0;
+    while i < letters.len() {
+        let pair = "fb".contains(letters[i])
+            && letters
+                .get(i + 1)
+                .is_some_and(|next| "lrvs".contains(*next));
+        let len = if pair { 2 } else { 1 };
+        groups.push(letters[i..i + len].to_vec());
+        i += len;
+    }
+    groups
+}
+
+// Whether a step goes every direction of one of the groups. Without any
+// groups every step is kept.
+fn keep(step: (i32, i32), groups: &[Vec<char>]) -> bool {
+    let (x, y) = step;
+    let goes = |d: &char| match d {
+        'f' => y > 0,
+        'b' => y < 0,
+        'l' => x < 0,
+        'r' => x > 0,
+        'v' => x.abs() < y.abs(),
+        's' => x.abs() > y.abs(),
+        _ => false,
+    };
+    groups.is_empty() || groups.iter().any(|group| group.iter().all(goes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(movements: &[Movement]) -> Vec<(i32, i32)> {
+        let mut steps: Vec<(i32, i32)> = movements.iter().map(|m| m.step).collect();
+        steps.sort();
+        steps
+    }
+
+    #[test]
+    fn knight() {
+        let movements = parse_betza("N").unwrap();
+        assert_eq!(
+            steps(&movements),
+            [
+                (-2, -1),
+                (-2, 1),
+                (-1, -2),
+                (-1, 2),
+                (1, -2),
+                (1, 2),
+                (2, -1),
+                (2, 1)
+            ]
+        );
+        assert!(movements
+            .iter()
+            .all(|m| m.range == 1 && m.mode == MoveMode::Both));
+    }
+
+    #[test]
+    fn pawn() {
+        let movements = parse_betza("fmWfcF").unwrap();
+        let expected = [
+            ((0, 1), MoveMode::MoveOnly),
+            ((1, 1), MoveMode::CaptureOnly),
+            ((-1, 1), MoveMode::CaptureOnly),
+        ];
+        assert_eq!(movements.len(), expected.len());
+        for (step, mode) in expected {
+            assert!(movements.contains(&Movement {
+                step,
+                range: 1,
+                mode
+            }));
+        }
+    }
+
+    #[test]
+    fn combined_directions() {
+        assert_eq!(steps(&parse_betza("flF").unwrap()), [(-1, 1)]);
+        assert_eq!(steps(&parse_betza("flbrF").unwrap()), [(-1, 1), (1, -1)]);
+        assert_eq!(steps(&parse_betza("fbW").unwrap()), [(0, -1), (0, 1)]);
+        // forward and more sideways than forward
+        assert_eq!(steps(&parse_betza("fsN").unwrap()), [(-2, 1), (2, 1)]);
+        // no rook step goes forward and right at once
+        assert!(parse_betza("frR").unwrap().is_empty());
+        let ranged = parse_betza("fR2").unwrap();
+        assert_eq!(steps(&ranged), [(0, 1)]);
+        assert_eq!(ranged[0].range, 2);
+    }
+
+    #[test]
+    fn fairy_builtins() {
+        let riders = |letter| {
+            let movements = piece_def(letter).unwrap().movements;
+            let riders = movements.iter().filter(|m| m.range == 0).count();
+            (movements.len(), riders)
+        };
+        // archbishop, chancellor and amazon ride and leap like a knight
+        assert_eq!(riders('A'), (12, 4));
+        assert_eq!(riders('C'), (12, 4));
+        assert_eq!(riders('M'), (16, 8));
+        let camel = steps(&piece_def('L').unwrap().movements);
+        assert_eq!(camel.len(), 8);
+        assert!(camel.contains(&(3, 1)) && camel.contains(&(-1, -3)));
+        let zebra = steps(&piece_def('Z').unwrap().movements);
+        assert_eq!(zebra.len(), 8);
+        assert!(zebra.contains(&(2, 3)) && zebra.contains(&(-3, -2)));
+    }
+}
@@ -0,0 +1,283 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{create_board, Board, GameState};
+
+// how much one puzzle moves the rating at most
+const RATING_STEP: f64 = 32.0;
+
+// A tactics puzzle. The first move is the opponent's, the one that sets up
+// the tactic, and the solver plays every other move after it.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    // in UCI, e.g. "e2e4"
+    pub moves: Vec<String>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+}
+
+// The solver's record, kept between sessions
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Score {
+    pub rating: f64,
+    pub solved: u32,
+    pub failed: u32,
+    // ids of the puzzles already tried, so they don't come up again
+    pub done: Vec<String>,
+}
+
+impl Default for Score {
+    fn default() -> Score {
+        Score {
+            rating: 1500.0,
+            solved: 0,
+            failed: 0,
+            done: vec![],
+        }
+    }
+}
+
+impl Score {
+    // The saved score, a new one when the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Score, String> {
+        if !path.exists() {
+            return Ok(Score::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Where the solver is in a puzzle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attempt {
+    Playing,
+    Solved,
+    Failed,
+}
+
+// Goes through puzzles one at a time, checks the solver's moves and keeps
+// the score
+#[derive(Clone, Debug)]
+pub struct Trainer {
+    pub puzzles: Vec<Puzzle>,
+    pub score: Score,
+    pub board: Board,
+    pub attempt: Attempt,
+    // the side the solver plays
+    pub solver: bool,
+    current: usize,
+    // moves of the solution played so far, the opponent's included
+    played: usize,
+    // a retry doesn't count for the score again
+    counted: bool,
+}
+
+impl Trainer {
+    // Starts with the puzzle that suits the score's rating best
+    pub fn new(puzzles: Vec<Puzzle>, score: Score) -> Result<Trainer, String> {
+        let first = puzzles.first().ok_or("No puzzles")?;
+        let board = create_board(Some(&first.fen))?;
+        let mut trainer = Trainer {
+            puzzles,
+            score,
+            board,
+            attempt: Attempt::Playing,
+            solver: true,
+            current: 0,
+            played: 0,
+            counted: false,
+        };
+        trainer.next_puzzle()?;
+        Ok(trainer)
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzles[self.current]
+    }
+
+    // Moves on to the untried puzzle closest to the rating, or to the
+    // closest of all once every one has been tried. Puzzles that don't
+    // start from a legal position are skipped.
+    pub fn next_puzzle(&mut self) -> Result<(), String> {
+        let rating = self.score.rating;
+        let mut order: Vec<usize> = (0..self.puzzles.len()).collect();
+        order.sort_by_key(|&i| {
+            let puzzle = &self.puzzles[i];
+            let done = self.score.done.contains(&puzzle.id);
+            (done, (puzzle.rating as f64 - rating).abs() as u32)
+        });
+        for i in order {
+            if self.start(i).is_ok() {
+                return Ok(());
+            }
+        }
+        Err("None of the puzzles can be played".to_string())
+    }
+
+    // Starts the current puzzle over, without counting it again
+    pub fn retry(&mut self) {
+        let _ = self.start(self.current);
+        self.counted = true;
+    }
+
+    fn start(&mut self, index: usize) -> Result<(), String> {
+        let puzzle = &self.puzzles[index];
+        let board = create_board(Some(&puzzle.fen))?;
+        board.parse_uci(puzzle.moves.first().ok_or("No moves")?)?;
+        self.solver = !board.turn;
+        self.board = board;
+        self.current = index;
+        self.played = 0;
+        self.attempt = Attempt::Playing;
+        self.counted = false;
+        Ok(())
+    }
+
+    // Whether the opponent's move is next, see reply
+    pub fn waiting(&self) -> bool {
+        self.attempt == Attempt::Playing && self.played.is_multiple_of(2)
+    }
+
+    // Plays the opponent's next move. Front ends wait a moment before
+    // calling it so the move can be followed.
+    pub fn reply(&mut self) -> bool {
+        if !self.waiting() {
+            return false;
+        }
+        let uci = &self.puzzles[self.current].moves[self.played];
+        let played = match self.board.parse_uci(uci) {
+            Ok((from, to, promotion)) => self.board.make_move(from, to, promotion),
+            Err(_) => false,
+        };
+        if !played {
+            // a broken puzzle counts as solved so it doesn't come up again
+            self.finish(Attempt::Solved);
+            return false;
+        }
+        self.played += 1;
+        true
+    }
+
+    // The solver's moves so far that were right
+    pub fn found(&self) -> usize {
+        self.played / 2
+    }
+
+    // The move the solution has next, in SAN
+    pub fn expected(&self) -> Option<String> {
+        let uci = self.puzzle().moves.get(self.played)?;
+        let (from, to, promotion) = self.board.parse_uci(uci).ok()?;
+        Some(self.board.san(from, to, promotion))
+    }
+
+    // Checks a move of the solver. It's right when it's the solution's move
+    // or when it mates, then the opponent's reply is next. A wrong move isn't
+    // played, the board stays so the right one can be shown.
+    pub fn play(&mut self, from: usize, to: usize, promotion: Option<char>) -> Attempt {
+        if self.attempt != Attempt::Playing
+            || self.waiting()
+            || !self.board.is_legal(from, to, promotion)
+        {
+            return self.attempt;
+        }
+        let moves = &self.puzzles[self.current].moves;
+        let expected = self.board.parse_uci(&moves[self.played]).ok();
+        let uci = |(from, to, promotion)| self.board.uci(from, to, promotion);
+        let right = expected.is_some_and(|mv| uci(mv) == uci((from, to, promotion)));
+        let mut after = self.board.clone();
+        after.make_move(from, to, promotion);
+        if !right && after.game_state != GameState::Checkmate {
+            self.finish(Attempt::Failed);
+            return self.attempt;
+        }
+        self.board = after;
+        self.played += 1;
+        if self.played >= moves.len() || self.board.game_state == GameState::Checkmate {
+            self.finish(Attempt::Solved);
+        }
+        self.attempt
+    }
+
+    // Ends the attempt and updates the score, Elo style against the
+    // puzzle's rating
+    fn finish(&mut self, attempt: Attempt) {
+        self.attempt = attempt;
+        if self.counted {
+            return;
+        }
+        self.counted = true;
+        let puzzle = &self.puzzles[self.current];
+        let expected = 1.0 / (1.0 + 10f64.powf((puzzle.rating as f64 - self.score.rating) / 400.0));
+        let result = if attempt == Attempt::Solved {
+            self.score.solved += 1;
+            1.0
+        } else {
+            self.score.failed += 1;
+            0.0
+        };
+        self.score.rating += RATING_STEP * (result - expected);
+        if !self.score.done.contains(&puzzle.id) {
+            self.score.done.push(puzzle.id.clone());
+        }
+    }
+}
+
+// Reads puzzles from a CSV file. With a header line the columns are found by
+// name as in the Lichess puzzle database (PuzzleId, FEN, Moves, Rating,
+// Themes), without one they are FEN, moves, rating and themes. Moves and
+// themes are separated by spaces.
+pub fn read_puzzles(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let header: Vec<String> = match lines.peek() {
+        Some(line) if line.contains("FEN") => {
+            let header = line
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .collect();
+            lines.next();
+            header
+        }
+        _ => ["FEN", "Moves", "Rating", "Themes"]
+            .map(String::from)
+            .to_vec(),
+    };
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(fen), Some(moves)) = (column("FEN"), column("Moves")) else {
+        return Err(format!("{}: no FEN or Moves column", path.display()));
+    };
+    let mut puzzles = vec![];
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |column: Option<usize>| column.and_then(|c| fields.get(c)).copied();
+        let (Some(fen), Some(moves)) = (field(Some(fen)), field(Some(moves))) else {
+            return Err(format!("{}: line {} is too short", path.display(), i + 1));
+        };
+        puzzles.push(Puzzle {
+            id: field(column("PuzzleId")).map_or_else(|| (i + 1).to_string(), String::from),
+            fen: fen.to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            rating: field(column("Rating"))
+                .and_then(|r| r.parse().ok())
+                .unwrap_or(1500),
+            themes: field(column("Themes"))
+                .map(|themes| themes.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+        });
+    }
+    Ok(puzzles)
+}
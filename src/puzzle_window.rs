@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use glutin_window::GlutinWindow;
+use opengl_graphics::GlGraphics;
+use piston::event_loop::Events;
+use piston::input::{Button, Key, MouseButton};
+use piston::{MouseCursorEvent, PressEvent, ReleaseEvent, RenderEvent, ResizeEvent, UpdateEvent};
+
+use chess::engine::Move;
+use chess::puzzle::{Attempt, Trainer};
+
+use crate::render::{self, View};
+use crate::theme::Skin;
+
+// seconds before the opponent's move, so it can be followed
+const REPLY_DELAY: f64 = 0.6;
+
+// Runs the puzzle trainer in the window until it's closed, saving the score
+// to `score_file` after every puzzle. N goes to the next puzzle, R starts this
+// one over and F flips the board.
+pub fn run(
+    window: &mut GlutinWindow,
+    events: &mut Events,
+    gl: &mut GlGraphics,
+    skin: &mut Skin,
+    mut view: View,
+    mut trainer: Trainer,
+    score_file: &Path,
+) {
+    let mut timer = 0.0;
+    view.flipped = !trainer.solver;
+
+    while let Some(e) = events.next(window) {
+        if let Some(args) = e.resize_args() {
+            view.layout = render::Layout::new(args.window_size);
+        }
+
+        if let Some(position) = e.mouse_cursor_args() {
+            view.mouse = position;
+        }
+
+        // any key takes a waiting promotion back
+        if let Some(Button::Keyboard(_)) = e.press_args() {
+            view.promoting = None;
+        }
+        match e.press_args() {
+            Some(Button::Keyboard(Key::N)) => {
+                if let Err(e) = trainer.next_puzzle() {
+                    eprintln!("{}", e);
+                }
+                view.flipped = !trainer.solver;
+                timer = 0.0;
+            }
+            Some(Button::Keyboard(Key::R)) => {
+                trainer.retry();
+                timer = 0.0;
+            }
+            Some(Button::Keyboard(Key::F)) => view.flipped = !view.flipped,
+            _ => {}
+        }
+
+        if let Some(args) = e.update_args() {
+            if trainer.waiting() {
+                timer += args.dt;
+                if timer >= REPLY_DELAY {
+                    trainer.reply();
+                    timer = 0.0;
+                    save(&trainer, score_file);
+                }
+            }
+        }
+
+        // the same clicking and dragging as on the game board, for the
+        // solver's pieces only
+        if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+            if let Some((from, to)) = view.promoting {
+                // a piece of the picker promotes to it, anywhere else takes the move back
+                let picked = render::promotion_at(view.mouse, &trainer.board, &view);
+                view.promoting = None;
+                if let Some(name) = picked {
+                    play(&mut trainer, &mut view, (from, to, Some(name)), score_file);
+                }
+            } else if let Some(pos) = render::square_at(view.mouse, &trainer.board, &view) {
+                let board = &trainer.board;
+                let target = board
+                    .selected
+                    .filter(|&sq| board.get_valid_moves(sq).contains(&pos));
+                let own_piece = board.board[pos]
+                    .as_ref()
+                    .is_some_and(|piece| piece.color() == trainer.solver);
+                let solving = trainer.attempt == Attempt::Playing && !trainer.waiting();
+                if let (Some(from), true) = (target, solving) {
+                    play(&mut trainer, &mut view, (from, pos, None), score_file);
+                } else if own_piece && solving {
+                    trainer.board.selected = Some(pos);
+                    view.dragging = Some(pos);
+                } else {
+                    trainer.board.selected = None;
+                }
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+            if let Some(from) = view.dragging.take() {
+                let to = render::square_at(view.mouse, &trainer.board, &view);
+                if let Some(to) = to.filter(|&to| to != from) {
+                    if trainer.board.get_valid_moves(from).contains(&to) {
+                        play(&mut trainer, &mut view, (from, to, None), score_file);
+                    }
+                }
+            }
+        }
+
+        if let Some(args) = e.render_args() {
+            view.layout = render::Layout::new(args.window_size);
+            gl.draw(args.viewport(), |c, g| {
+                graphics::clear(skin.theme.background.0, g);
+                render::draw_board(&c, g, trainer.board.clone(), skin, &view);
+                render::draw_puzzle_panel(&c, g, &trainer, skin, &view);
+            });
+        }
+    }
+    save(&trainer, score_file);
+}
+
+// A move by the solver. A pawn reaching the last rank without a piece
+// waits for the promotion picker.
+fn play(trainer: &mut Trainer, view: &mut View, mv: Move, score_file: &Path) {
+    let (from, to, promotion) = mv;
+    trainer.board.selected = None;
+    if promotion.is_none() && trainer.board.is_promotion(from, to) {
+        view.promoting = Some((from, to));
+        return;
+    }
+    if trainer.play(from, to, promotion) != Attempt::Playing {
+        save(trainer, score_file);
+    }
+}
+
+fn save(trainer: &Trainer, score_file: &Path) {
+    if let Err(e) = trainer.score.save(score_file) {
+        eprintln!("Could not save the puzzle score: {}", e);
+    }
+}
@@ -1,107 +1,1182 @@
-use graphics::Ellipse;
 use graphics::{Context, Graphics};
+use graphics::{Ellipse, Image, Line, Polygon};
 use graphics::{Rectangle, Transformed};
 
 use opengl_graphics::*;
 
-pub use crate::lib::Board;
-use crate::SCREEN_HEIGHT;
+use graphics::character::CharacterCache;
 
-const COLOR1: [f32; 4] = [0.45, 0.4, 0.4, 1.0];
-const COLOR2: [f32; 4] = [0.9, 0.8, 0.75, 1.0];
-const CELL_SIZE: usize = SCREEN_HEIGHT / 8;
+use crate::editor::{Editor, EditorItem};
+use crate::theme::Skin;
+use chess::book::Book;
+use chess::puzzle::{Attempt, Trainer};
+use chess::retrograde::{self, Distance};
+use chess::{piece_letters, unicode_glyph, Board, Bughouse, Game, GameState, Highlight};
+
+// Everything is laid out for a 600x600 board with the panel next to it,
+// then scaled to fit the window
+pub const DESIGN_SIZE: [f64; 2] = [860.0, 600.0];
+const BOARD_SIZE: f64 = 600.0;
+
+// The side panel to the right of the board
+const PANEL_X: f64 = BOARD_SIZE + 16.0;
+const TRAY_HEIGHT: f64 = 40.0;
+const ROW_HEIGHT: f64 = 24.0;
+const LIST_TOP: f64 = TRAY_HEIGHT + 16.0;
+// how many move list rows fit between the trays, the menu and the status line
+pub const MOVE_ROWS: usize =
+    ((BOARD_SIZE - 2.0 * LIST_TOP - 2.0 * ROW_HEIGHT) / ROW_HEIGHT) as usize;
+// the row of menu buttons under the move list
+const MENU_Y: f64 = LIST_TOP + MOVE_ROWS as f64 * ROW_HEIGHT + 4.0;
+const MENU_WIDTH: f64 = 44.0;
+
+// Commands in the panel menu, they also have keyboard shortcuts
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuItem {
+    New,
+    Save,
+    Load,
+    Fen,
+    Setup,
+}
+
+pub const MENU: [(MenuItem, &str); 5] = [
+    (MenuItem::New, "New"),
+    (MenuItem::Save, "Save"),
+    (MenuItem::Load, "Load"),
+    (MenuItem::Fen, "FEN"),
+    (MenuItem::Setup, "Edit"),
+];
+
+// The setup panel: rows of palette pieces for each color and rows of buttons
+// under them
+const PALETTE_CELL: f64 = 40.0;
+const PALETTE_COLUMNS: usize = 6;
+const BUTTON_WIDTH: f64 = 56.0;
+
+// Everything about how the board is shown that isn't part of the game itself
+pub struct View {
+    // black at the bottom
+    pub flipped: bool,
+    // square of the piece being dragged, it's drawn under the mouse instead
+    pub dragging: Option<usize>,
+    // crazyhouse piece being dragged out of a pocket
+    pub dropping: Option<char>,
+    // a pawn move onto the last rank waiting for a piece from the picker
+    pub promoting: Option<(usize, usize)>,
+    pub mouse: [f64; 2],
+    // tint the squares the opponent attacks
+    pub show_threats: bool,
+    // first move list row shown in the panel
+    pub scroll: usize,
+    pub layout: Layout,
+    // shown instead of the status line, e.g. after saving
+    pub message: Option<String>,
+    // the engine's suggestion, drawn as an arrow
+    pub hint: Option<(usize, usize)>,
+    // drawn by the user with the right mouse button
+    pub arrows: Vec<(Highlight, usize, usize)>,
+    pub marks: Vec<(Highlight, usize)>,
+}
+
+// Where the design area ends up in the window. It keeps its aspect ratio
+// and is centered, so the board always stays square.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    pub origin: [f64; 2],
+    pub scale: f64,
+}
+
+impl Layout {
+    pub fn new(window: [f64; 2]) -> Layout {
+        let scale = (window[0] / DESIGN_SIZE[0]).min(window[1] / DESIGN_SIZE[1]);
+        Layout {
+            origin: [
+                (window[0] - DESIGN_SIZE[0] * scale) / 2.0,
+                (window[1] - DESIGN_SIZE[1] * scale) / 2.0,
+            ],
+            scale,
+        }
+    }
+
+    // From window coordinates to design coordinates
+    fn design_point(&self, point: [f64; 2]) -> [f64; 2] {
+        [
+            (point[0] - self.origin[0]) / self.scale,
+            (point[1] - self.origin[1]) / self.scale,
+        ]
+    }
+
+    // Drawing transform that maps design coordinates into the window
+    fn context(&self, c: &Context) -> Context {
+        c.trans(self.origin[0], self.origin[1])
+            .scale(self.scale, self.scale)
+    }
+
+    // Fonts are rasterized at the scaled size so they stay sharp
+    fn font_size(&self, size: u32) -> u32 {
+        ((size as f64 * self.scale).round() as u32).max(1)
+    }
+}
 
 pub fn draw_board<G: Graphics<Texture = Texture>>(
     c: &Context,
     g: &mut G,
-    mut board: Board,
-    glyphs: &mut GlyphCache,
+    board: Board,
+    skin: &mut Skin,
+    view: &View,
 ) {
-    let mut cnt = 0;
-    for i in 0..64 {
-        let mut color: [f32; 4] = COLOR1;
-        if (i + cnt) % 2 == 0 {
-            color = COLOR2;
-        }
-        if (i % 8) == 7 {
-            cnt += 1;
+    let theme = skin.theme.clone();
+    let layout = view.layout;
+    let c = &layout.context(c);
+    let mut targets = vec![];
+    if let Some(sq) = board.selected {
+        targets = board.get_valid_moves(sq);
+    }
+    if let Some(name) = view.dropping {
+        targets = (0..board.size())
+            .filter(|&to| board.can_drop(name, to))
+            .collect();
+    }
+    let threats = board.attacked_squares(!board.turn);
+    let checked_king = if board.in_check(board.turn) {
+        board.king_square(board.turn)
+    } else {
+        None
+    };
+
+    let cell = cell_size(&board);
+    let width = board.width;
+    for i in 0..board.size() {
+        let mut color: [f32; 4] = theme.dark.0;
+        let mut label_color = theme.light.0;
+        if (board.file(i) + board.row(i)) % 2 == 0 {
+            color = theme.light.0;
+            label_color = theme.dark.0;
         }
-        let x = (i % 8) * CELL_SIZE;
-        let y = (i / 8) * CELL_SIZE;
+        let on_screen = view_square(&board, i, view.flipped);
+        let x = (on_screen % width) as f64 * cell;
+        let y = (on_screen / width) as f64 * cell;
 
         let mut draw_circle = false;
-        let mut circle_color = [0.7, 0.7, 0.7, 1.0];
+        let mut circle_color = theme.move_hint.0;
 
-        if let Some(sq) = board.selected {
-            if let Some(_sq2) = &board.board[sq] {
-                if board.get_valid_moves(sq).contains(&i) {
-                    if let Some(_p) = &board.board[i] {
-                        circle_color = [0.6, 0.3, 0.3, 1.0];
-                    }
-                    draw_circle = true;
-                }
+        if let Some((from, to)) = board.last_move {
+            if i == from || i == to {
+                color = mix(color, theme.last_move.0, 0.5);
             }
-            if sq == i {
-                color = [0.3, 0.6, 0.3, 1.0];
+        }
+
+        if targets.contains(&i) {
+            if let Some(_p) = &board.board[i] {
+                circle_color = theme.capture_hint.0;
             }
+            draw_circle = true;
+        }
+        if board.selected == Some(i) {
+            color = theme.selected.0;
         }
 
-        Rectangle::new(color).draw(
-            [x as f64, y as f64, CELL_SIZE as f64, CELL_SIZE as f64],
-            &c.draw_state,
-            c.transform,
-            g,
-        );
+        Rectangle::new(color).draw([x, y, cell, cell], &c.draw_state, c.transform, g);
 
-        if let Some(piece) = &board.board[i] {
-            let name;
-            match piece.name.to_string().as_str() {
-                // White pieces
-                "P" => name = '\u{2659}', // White Pawn
-                "R" => name = '\u{2656}', // White Rook
-                "N" => name = '\u{2658}', // White Knight
-                "B" => name = '\u{2657}', // White Bishop
-                "Q" => name = '\u{2655}', // White Queen
-                "K" => name = '\u{2654}', // White King
-
-                // Black pieces
-                "p" => name = '\u{265F}', // Black Pawn
-                "r" => name = '\u{265C}', // Black Rook
-                "n" => name = '\u{265E}', // Black Knight
-                "b" => name = '\u{265D}', // Black Bishop
-                "q" => name = '\u{265B}', // Black Queen
-                "k" => name = '\u{265A}', // Black King
-                _ => name = ' ',
-            }
-            let piece_transform = c.transform.trans(
-                x as f64 + CELL_SIZE as f64 / 2.0 - 24.0, // 24 is about half the width of the character
-                y as f64 + CELL_SIZE as f64 / 2.0 + 24.0,
+        // rank numbers down the left edge and file letters along the bottom
+        if on_screen.is_multiple_of(width) {
+            let rank = board.height - board.row(i);
+            draw_text(
+                c,
+                g,
+                &mut skin.glyphs,
+                &rank.to_string(),
+                layout,
+                14,
+                label_color,
+                [x + 3.0, y + 15.0],
             );
-            // A transform to move the placement of the text
-            graphics::text::Text::new(50)
-                .draw(&name.to_string(), glyphs, &c.draw_state, piece_transform, g)
-                .unwrap();
+        }
+        if on_screen / width == board.height - 1 {
+            let file = (b'a' + board.file(i) as u8) as char;
+            draw_text(
+                c,
+                g,
+                &mut skin.glyphs,
+                &file.to_string(),
+                layout,
+                14,
+                label_color,
+                [x + cell - 11.0, y + cell - 4.0],
+            );
+        }
+
+        if view.show_threats && threats.contains(&i) {
+            Rectangle::new(theme.threat.0).draw([x, y, cell, cell], &c.draw_state, c.transform, g);
+        }
 
-            // For some reason this is needed for the text to render correctly
-            // draws a space in another color
-            graphics::text::Text::new_color([1.0; 4], 32)
-                .draw(" ", glyphs, &c.draw_state, c.transform, g)
-                .unwrap();
+        // red glow under the king in check, fading out towards the edge
+        if checked_king == Some(i) {
+            for ring in 0..4 {
+                let inset = ring as f64 * cell / 10.0;
+                let mut glow = theme.check.0;
+                glow[3] = 0.25;
+                Ellipse::new(glow).draw(
+                    [x + inset, y + inset, cell - 2.0 * inset, cell - 2.0 * inset],
+                    &c.draw_state,
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        if let Some(piece) = &board.board[i] {
+            if view.dragging != Some(i) {
+                draw_piece(
+                    c,
+                    g,
+                    skin,
+                    piece.name,
+                    layout,
+                    [x + cell / 2.0, y + cell / 2.0],
+                    cell,
+                );
+            }
         }
 
         //draw the circle on top of everything else
         if draw_circle {
             Ellipse::new(circle_color).draw(
-                [
-                    (x + CELL_SIZE / 3) as f64,
-                    (y + CELL_SIZE / 3) as f64,
-                    (CELL_SIZE / 3) as f64,
-                    (CELL_SIZE / 3) as f64,
-                ],
+                [x + cell / 3.0, y + cell / 3.0, cell / 3.0, cell / 3.0],
                 &c.draw_state,
                 c.transform,
                 g,
             );
         }
     }
+
+    for &(color, square) in &view.marks {
+        let on_screen = view_square(&board, square, view.flipped);
+        let x = (on_screen % width) as f64 * cell;
+        let y = (on_screen / width) as f64 * cell;
+        let ring = cell / 16.0;
+        Ellipse::new_border(highlight_color(color), ring).draw(
+            [x + ring, y + ring, cell - 2.0 * ring, cell - 2.0 * ring],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+    }
+    for &(color, from, to) in &view.arrows {
+        draw_arrow(c, g, &board, view, from, to, highlight_color(color));
+    }
+    if let Some((from, to)) = view.hint {
+        draw_arrow(c, g, &board, view, from, to, theme.arrow.0);
+    }
+
+    // the dragged piece goes on top of everything, centered on the mouse
+    if let Some(sq) = view.dragging {
+        if let Some(piece) = &board.board[sq] {
+            let mouse = layout.design_point(view.mouse);
+            draw_piece(c, g, skin, piece.name, layout, mouse, cell);
+        }
+    }
+    if let Some(name) = view.dropping {
+        let mouse = layout.design_point(view.mouse);
+        draw_piece(c, g, skin, name, layout, mouse, cell);
+    }
+
+    // the promotion picker over a dimmed board
+    let picker = promotion_cells(&board, view);
+    if !picker.is_empty() {
+        let mut shade = theme.background.0;
+        shade[3] = 0.6;
+        let whole = [0.0, 0.0, width as f64 * cell, board.height as f64 * cell];
+        Rectangle::new(shade).draw(whole, &c.draw_state, c.transform, g);
+    }
+    for (name, rect) in picker {
+        Ellipse::new(theme.light.0).draw(rect, &c.draw_state, c.transform, g);
+        let center = [rect[0] + rect[2] / 2.0, rect[1] + rect[3] / 2.0];
+        draw_piece(c, g, skin, name, layout, center, cell);
+    }
+}
+
+// The pieces of the promotion picker with their rectangles: a column from
+// the promotion square towards the middle of the board, going on in the next
+// column over when the board isn't high enough
+fn promotion_cells(board: &Board, view: &View) -> Vec<(char, [f64; 4])> {
+    let Some((from, to)) = view.promoting else {
+        return vec![];
+    };
+    let color = board.board[from]
+        .as_ref()
+        .map_or(board.turn, |piece| piece.color());
+    let cell = cell_size(board);
+    let on_screen = view_square(board, to, view.flipped);
+    let (column, row) = (on_screen % board.width, on_screen / board.width);
+    let down = row < board.height / 2;
+    let inward = column < board.width / 2;
+    board
+        .promotion_pieces()
+        .iter()
+        .enumerate()
+        .map(|(i, &name)| {
+            let (step, across) = (i % board.height, i / board.height);
+            let y = if down { row + step } else { row - step };
+            let x = if inward {
+                column + across
+            } else {
+                column - across
+            };
+            let name = if color {
+                name.to_ascii_uppercase()
+            } else {
+                name
+            };
+            (name, [x as f64 * cell, y as f64 * cell, cell, cell])
+        })
+        .collect()
+}
+
+// The piece of the promotion picker under a point, as the promotion letter
+pub fn promotion_at(point: [f64; 2], board: &Board, view: &View) -> Option<char> {
+    let point = view.layout.design_point(point);
+    promotion_cells(board, view)
+        .into_iter()
+        .find(|(_, rect)| {
+            (rect[0]..rect[0] + rect[2]).contains(&point[0])
+                && (rect[1]..rect[1] + rect[3]).contains(&point[1])
+        })
+        .map(|(name, _)| name.to_ascii_lowercase())
+}
+
+// Captured pieces, move list and game status to the right of the board
+pub fn draw_panel<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    game: &Game,
+    skin: &mut Skin,
+    view: &View,
+    book: Option<&Book>,
+) {
+    let text_color = skin.theme.text.0;
+    let current_color = skin.theme.current_move.0;
+    let layout = view.layout;
+    let c = &layout.context(c);
+    let crazyhouse = game.board().variant.has_drops();
+    if crazyhouse {
+        let trays = panel_trays(view);
+        draw_pockets(c, g, game.board(), skin, layout, trays, view.dropping);
+    }
+    let glyphs = &mut skin.glyphs;
+    // each tray shows what that side has taken, on the same side as their pieces
+    let top_color = view.flipped;
+    let difference = game.material_difference();
+    let trays = if crazyhouse {
+        vec![]
+    } else {
+        vec![(top_color, 0.0), (!top_color, BOARD_SIZE - TRAY_HEIGHT)]
+    };
+    for (color, y) in trays {
+        let mut text: String = game.captured(!color).iter().map(|p| p.glyph()).collect();
+        let advantage = if color { difference } else { -difference };
+        if advantage > 0 {
+            text.push_str(&format!(" +{}", advantage));
+        }
+        draw_text(
+            c,
+            g,
+            glyphs,
+            &text,
+            layout,
+            24,
+            text_color,
+            [PANEL_X, y + 30.0],
+        );
+    }
+
+    let moves = game.moves();
+    for row in 0..MOVE_ROWS {
+        let number = row + view.scroll;
+        if number * 2 >= moves.len() {
+            break;
+        }
+        let y = LIST_TOP + row as f64 * ROW_HEIGHT;
+        for side in 0..2 {
+            let index = number * 2 + side;
+            if let Some(mv) = moves.get(index) {
+                let x = move_column_x(side);
+                if game.current() == index + 1 {
+                    Rectangle::new(current_color).draw(
+                        [x - 4.0, y, 90.0, ROW_HEIGHT],
+                        &c.draw_state,
+                        c.transform,
+                        g,
+                    );
+                }
+                draw_text(c, g, glyphs, &mv.san, layout, 18, text_color, [x, y + 18.0]);
+            }
+        }
+        draw_text(
+            c,
+            g,
+            glyphs,
+            &format!("{}.", number + 1),
+            layout,
+            18,
+            text_color,
+            [PANEL_X, y + 18.0],
+        );
+    }
+
+    for (i, (_, label)) in MENU.iter().enumerate() {
+        let x = PANEL_X + i as f64 * (MENU_WIDTH + 4.0);
+        Rectangle::new(current_color).draw(
+            [x, MENU_Y, MENU_WIDTH, ROW_HEIGHT - 2.0],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+        draw_text(
+            c,
+            g,
+            glyphs,
+            label,
+            layout,
+            16,
+            text_color,
+            [x + 6.0, MENU_Y + 17.0],
+        );
+    }
+
+    let board = game.board();
+    let side = if board.turn { "White" } else { "Black" };
+    let status = match board.game_state {
+        GameState::Checkmate => format!(
+            "Checkmate, {} wins",
+            if board.turn { "black" } else { "white" }
+        ),
+        GameState::Stalemate => "Stalemate".to_string(),
+        GameState::GameOver => "Game over".to_string(),
+        GameState::Won(color) => format!(
+            "{}, {} wins",
+            board.variant,
+            if color { "white" } else { "black" }
+        ),
+        GameState::Draw => format!("{}, draw", board.variant),
+        GameState::Check => format!("{} to move, check", side),
+        GameState::InProgress => format!("{} to move", side),
+    };
+    // alternatives to the next move take the place of the status, Down enters them
+    let variations: Vec<&str> = game.variations().iter().map(|mv| mv.san.as_str()).collect();
+    let status = if variations.is_empty() {
+        status
+    } else {
+        format!("Variations: {}", variations.join(" "))
+    };
+    // then the book moves of the position with how often the book plays them
+    let book_moves = book.map(|book| book.moves(board)).unwrap_or_default();
+    let total: u32 = book_moves.iter().map(|&(_, weight)| weight as u32).sum();
+    let status_free = book_moves.is_empty() && variations.is_empty();
+    let status = if book_moves.is_empty() || !variations.is_empty() {
+        status
+    } else {
+        let moves: Vec<String> = book_moves
+            .iter()
+            .take(5)
+            .map(|&((from, to, promotion), weight)| {
+                let share = (weight as u32 * 100).checked_div(total).unwrap_or(0);
+                format!("{} {}%", board.san(from, to, promotion), share)
+            })
+            .collect();
+        format!("Book: {}", moves.join(", "))
+    };
+    // and what the endgame tables say, once the game is down to a few pieces
+    let table = retrograde::probe(board).filter(|_| !board.is_finished());
+    let status = match table {
+        Some(distance) if status_free => {
+            let winner = match distance {
+                Distance::Win(_) => side,
+                _ => {
+                    if board.turn {
+                        "Black"
+                    } else {
+                        "White"
+                    }
+                }
+            };
+            match distance {
+                Distance::Draw => "Endgame table: draw".to_string(),
+                _ => format!("Endgame table: {} mates in {}", winner, distance.moves()),
+            }
+        }
+        _ => status,
+    };
+    let status = view.message.clone().unwrap_or(status);
+    let y = BOARD_SIZE - LIST_TOP;
+    draw_text(c, g, glyphs, &status, layout, 18, text_color, [PANEL_X, y]);
+}
+
+// The puzzle, the solver's progress and the score next to the board
+pub fn draw_puzzle_panel<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    trainer: &Trainer,
+    skin: &mut Skin,
+    view: &View,
+) {
+    let text_color = skin.theme.text.0;
+    let layout = view.layout;
+    let c = &layout.context(c);
+    let puzzle = trainer.puzzle();
+    let side = if trainer.solver { "white" } else { "black" };
+    let status = match trainer.attempt {
+        Attempt::Solved => "Solved! N for the next one".to_string(),
+        Attempt::Failed => format!(
+            "Wrong, {} was the move",
+            trainer.expected().unwrap_or_default()
+        ),
+        Attempt::Playing if trainer.found() > 0 => "Right, keep going".to_string(),
+        Attempt::Playing => format!("Find the best move for {}", side),
+    };
+    let score = &trainer.score;
+    let lines = [
+        format!("Puzzle {}, rated {}", puzzle.id, puzzle.rating),
+        status,
+        String::new(),
+        format!("Your rating: {:.0}", score.rating),
+        format!("Solved {} of {}", score.solved, score.solved + score.failed),
+        String::new(),
+        "N next puzzle, R retry".to_string(),
+        "F flips the board".to_string(),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        let y = LIST_TOP + i as f64 * ROW_HEIGHT;
+        draw_text(
+            c,
+            g,
+            &mut skin.glyphs,
+            line,
+            layout,
+            16,
+            text_color,
+            [PANEL_X, y],
+        );
+    }
+    // the themes a few to a line, under the rest
+    for (i, themes) in puzzle.themes.chunks(2).take(4).enumerate() {
+        let y = LIST_TOP + (lines.len() + 1 + i) as f64 * ROW_HEIGHT;
+        let text = themes.join(", ");
+        draw_text(
+            c,
+            g,
+            &mut skin.glyphs,
+            &text,
+            layout,
+            14,
+            text_color,
+            [PANEL_X, y],
+        );
+    }
+}
+
+// Scrolls the move list so the current move is in view
+pub fn scroll_to_current(view: &mut View, game: &Game) {
+    let row = game.current().saturating_sub(1) / 2;
+    if row < view.scroll {
+        view.scroll = row;
+    } else if row >= view.scroll + MOVE_ROWS {
+        view.scroll = row + 1 - MOVE_ROWS;
+    }
+}
+
+// Crazyhouse trays: the pieces each side has in hand, with how many of each.
+// `dropping` is the piece being dragged out of them, if any.
+#[allow(clippy::too_many_arguments)]
+fn draw_pockets<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    board: &Board,
+    skin: &mut Skin,
+    layout: Layout,
+    trays: [(bool, [f64; 2]); 2],
+    dropping: Option<char>,
+) {
+    let text_color = skin.theme.text.0;
+    for (name, count, rect) in pocket_cells(board, trays) {
+        // one of them is being dragged out
+        let count = if dropping == Some(name) {
+            count - 1
+        } else {
+            count
+        };
+        if count == 0 {
+            continue;
+        }
+        let center = [rect[0] + rect[2] / 2.0, rect[1] + rect[3] / 2.0];
+        draw_piece(c, g, skin, name, layout, center, TRAY_HEIGHT);
+        if count > 1 {
+            draw_text(
+                c,
+                g,
+                &mut skin.glyphs,
+                &count.to_string(),
+                layout,
+                12,
+                text_color,
+                [rect[0] + rect[2] - 9.0, rect[1] + rect[3] - 2.0],
+            );
+        }
+    }
+}
+
+// The panel trays for each color, the top one for the color at the top of the board
+fn panel_trays(view: &View) -> [(bool, [f64; 2]); 2] {
+    let top_color = view.flipped;
+    [
+        (top_color, [PANEL_X, 0.0]),
+        (!top_color, [PANEL_X, BOARD_SIZE - TRAY_HEIGHT]),
+    ]
+}
+
+// The pieces in both pockets with their counts and rectangles, each in the
+// tray of its color
+fn pocket_cells(board: &Board, trays: [(bool, [f64; 2]); 2]) -> Vec<(char, usize, [f64; 4])> {
+    let mut cells = vec![];
+    for (color, [x, y]) in trays {
+        for (i, (name, count)) in board.pocket(color).into_iter().enumerate() {
+            let x = x + i as f64 * TRAY_HEIGHT;
+            cells.push((name, count, [x, y, TRAY_HEIGHT, TRAY_HEIGHT]));
+        }
+    }
+    cells
+}
+
+// The pocket piece whose cell contains a design point
+fn pocket_cell_at(board: &Board, trays: [(bool, [f64; 2]); 2], point: [f64; 2]) -> Option<char> {
+    pocket_cells(board, trays)
+        .into_iter()
+        .find(|(_, _, rect)| {
+            (rect[0]..rect[0] + rect[2]).contains(&point[0])
+                && (rect[1]..rect[1] + rect[3]).contains(&point[1])
+        })
+        .map(|(name, _, _)| name)
+}
+
+// The pocket piece under a point, whichever side it belongs to
+pub fn pocket_at(point: [f64; 2], board: &Board, view: &View) -> Option<char> {
+    pocket_cell_at(board, panel_trays(view), view.layout.design_point(point))
+}
+
+// Bughouse shows both boards side by side at two thirds of the size, with
+// each player's pocket and clock next to their side of the board
+const BUGHOUSE_BOARD: f64 = 400.0;
+const BUGHOUSE_TOP: f64 = TRAY_HEIGHT + 24.0;
+const CLOCK_WIDTH: f64 = 64.0;
+
+fn bughouse_x(board: usize) -> f64 {
+    20.0 + board as f64 * (BUGHOUSE_BOARD + 20.0)
+}
+
+// The view of one bughouse board, scaled down into its place. The second
+// board is turned around so both teams sit on the same side of both boards.
+// Only the `active` board shows the piece being dragged.
+pub fn bughouse_view(view: &View, board: usize, active: bool) -> View {
+    let layout = view.layout;
+    View {
+        flipped: view.flipped != (board == 1),
+        dragging: view.dragging.filter(|_| active),
+        dropping: view.dropping.filter(|_| active),
+        promoting: view.promoting.filter(|_| active),
+        mouse: view.mouse,
+        show_threats: view.show_threats,
+        scroll: 0,
+        layout: Layout {
+            origin: [
+                layout.origin[0] + bughouse_x(board) * layout.scale,
+                layout.origin[1] + BUGHOUSE_TOP * layout.scale,
+            ],
+            scale: layout.scale * BUGHOUSE_BOARD / BOARD_SIZE,
+        },
+        message: None,
+        hint: None,
+        arrows: vec![],
+        marks: vec![],
+    }
+}
+
+// The trays above and below a bughouse board, for the colors sitting there
+fn bughouse_trays(board: usize, flipped: bool) -> [(bool, [f64; 2]); 2] {
+    let x = bughouse_x(board);
+    [
+        (flipped, [x, BUGHOUSE_TOP - TRAY_HEIGHT - 4.0]),
+        (!flipped, [x, BUGHOUSE_TOP + BUGHOUSE_BOARD + 4.0]),
+    ]
+}
+
+// Both boards with their pockets and clocks, and the result under them.
+// `active` is the board a piece is being dragged on.
+pub fn draw_bughouse<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    bughouse: &Bughouse,
+    skin: &mut Skin,
+    view: &View,
+    active: Option<usize>,
+) {
+    let text_color = skin.theme.text.0;
+    let current_color = skin.theme.current_move.0;
+    let layout = view.layout;
+    let result = bughouse.result();
+    // the active board goes last so the dragged piece stays on top of the other one
+    let order = if active == Some(0) { [1, 0] } else { [0, 1] };
+    for i in order {
+        let board = &bughouse.boards[i];
+        let sub = bughouse_view(view, i, active == Some(i));
+        draw_board(c, g, board.clone(), skin, &sub);
+        let c = &layout.context(c);
+        let trays = bughouse_trays(i, sub.flipped);
+        draw_pockets(c, g, board, skin, layout, trays, sub.dropping);
+        for (color, [x, y]) in trays {
+            let rect = [
+                x + BUGHOUSE_BOARD - CLOCK_WIDTH,
+                y,
+                CLOCK_WIDTH,
+                TRAY_HEIGHT,
+            ];
+            if board.turn == color && result.is_none() {
+                Rectangle::new(current_color).draw(rect, &c.draw_state, c.transform, g);
+            }
+            let seconds = bughouse.clock(i, color).as_secs();
+            draw_text(
+                c,
+                g,
+                &mut skin.glyphs,
+                &format!("{:02}:{:02}", seconds / 60, seconds % 60),
+                layout,
+                20,
+                text_color,
+                [rect[0] + 6.0, y + 28.0],
+            );
+        }
+    }
+    let c = &layout.context(c);
+    let status = match result {
+        Some(outcome) => match outcome.winner {
+            Some(team) => format!("{}, team {} wins", outcome.reason, team + 1),
+            None => format!("{}, draw", outcome.reason),
+        },
+        None => "Team 1 is white on board 1 and black on board 2".to_string(),
+    };
+    let status = view.message.clone().unwrap_or(status);
+    let y = BOARD_SIZE - 24.0;
+    draw_text(
+        c,
+        g,
+        &mut skin.glyphs,
+        &status,
+        layout,
+        18,
+        text_color,
+        [20.0, y],
+    );
+}
+
+// The bughouse board and square under a point in the window
+pub fn bughouse_square_at(
+    point: [f64; 2],
+    bughouse: &Bughouse,
+    view: &View,
+) -> Option<(usize, usize)> {
+    (0..2).find_map(|i| {
+        let sub = bughouse_view(view, i, false);
+        square_at(point, &bughouse.boards[i], &sub).map(|pos| (i, pos))
+    })
+}
+
+// The bughouse board and pocket piece under a point in the window
+pub fn bughouse_pocket_at(
+    point: [f64; 2],
+    bughouse: &Bughouse,
+    view: &View,
+) -> Option<(usize, char)> {
+    let point = view.layout.design_point(point);
+    (0..2).find_map(|i| {
+        let trays = bughouse_trays(i, bughouse_view(view, i, false).flipped);
+        pocket_cell_at(&bughouse.boards[i], trays, point).map(|name| (i, name))
+    })
+}
+
+fn move_column_x(side: usize) -> f64 {
+    PANEL_X + 44.0 + side as f64 * 96.0
+}
+
+// The move in the move list under a point, as an index into `Game::moves`
+pub fn move_at(point: [f64; 2], view: &View) -> Option<usize> {
+    let point = view.layout.design_point(point);
+    let row = ((point[1] - LIST_TOP) / ROW_HEIGHT).floor();
+    if point[1] < LIST_TOP || row >= MOVE_ROWS as f64 || point[0] >= DESIGN_SIZE[0] {
+        return None;
+    }
+    let side = if point[0] >= move_column_x(1) - 4.0 {
+        1
+    } else if point[0] >= move_column_x(0) - 4.0 {
+        0
+    } else {
+        return None;
+    };
+    Some((row as usize + view.scroll) * 2 + side)
+}
+
+// Every button of the setup panel with its rectangle and label
+fn editor_buttons(editor: &Editor) -> Vec<(EditorItem, [f64; 4], String)> {
+    let mut buttons = vec![];
+    // every piece defined, the fairy pieces after the usual six
+    let letters = piece_letters();
+    let mut y = 8.0;
+    for color in [true, false] {
+        for pieces in letters.chunks(PALETTE_COLUMNS) {
+            for (i, &letter) in pieces.iter().enumerate() {
+                let name = if color {
+                    letter
+                } else {
+                    letter.to_ascii_lowercase()
+                };
+                let x = PANEL_X + i as f64 * PALETTE_CELL;
+                buttons.push((
+                    EditorItem::Piece(name),
+                    [x, y, PALETTE_CELL, PALETTE_CELL],
+                    String::new(),
+                ));
+            }
+            y += PALETTE_CELL + 4.0;
+        }
+    }
+    let board = &editor.board;
+    let en_passant = match board.en_passant {
+        Some(sq) => format!("e.p. {}", board.square_name(sq)),
+        None => "e.p. -".to_string(),
+    };
+    let mut rows: Vec<Vec<(EditorItem, String)>> = vec![vec![
+        (
+            EditorItem::Turn,
+            if board.turn { "White" } else { "Black" }.to_string(),
+        ),
+        (EditorItem::Erase, "Erase".to_string()),
+        (EditorItem::Clear, "Clear".to_string()),
+        (EditorItem::Reset, "Reset".to_string()),
+    ]];
+    // a castling toggle for each rook that could castle, named after its
+    // square, white's first
+    let back_rank = |color| (board.back_row(color) * board.width..).take(board.width);
+    let rooks: Vec<(EditorItem, String)> = back_rank(true)
+        .chain(back_rank(false))
+        .filter(|&sq| board.can_castle_with(sq))
+        .map(|sq| (EditorItem::Castling(sq), board.square_name(sq)))
+        .collect();
+    rows.extend(rooks.chunks(4).map(|row| row.to_vec()));
+    rows.push(vec![(EditorItem::EnPassant, en_passant)]);
+    if board.variant.has_drops() {
+        rows.push(vec![
+            (EditorItem::AddToPocket, "+ Pocket".to_string()),
+            (EditorItem::TakeFromPocket, "- Pocket".to_string()),
+        ]);
+    }
+    rows.push(vec![
+        (EditorItem::Play, "Play".to_string()),
+        (EditorItem::Cancel, "Cancel".to_string()),
+    ]);
+    y += 8.0;
+    for items in rows {
+        let mut x = PANEL_X;
+        for (item, label) in items {
+            // longer labels get two buttons' room
+            let width = if label.len() > 6 {
+                2.0 * BUTTON_WIDTH + 4.0
+            } else {
+                BUTTON_WIDTH
+            };
+            buttons.push((item, [x, y, width, ROW_HEIGHT], label));
+            x += width + 4.0;
+        }
+        y += ROW_HEIGHT + 4.0;
+    }
+    buttons
+}
+
+// The setup panel in place of the move list: the palette, the toggles for
+// the rest of the position, its FEN and whether it's legal
+pub fn draw_editor_panel<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    editor: &Editor,
+    skin: &mut Skin,
+    view: &View,
+) {
+    let layout = view.layout;
+    let c = &layout.context(c);
+    let theme = skin.theme.clone();
+    let buttons = editor_buttons(editor);
+    let bottom = buttons
+        .iter()
+        .map(|(_, rect, _)| rect[1] + rect[3])
+        .fold(0.0, f64::max);
+    for (item, rect, label) in buttons {
+        let active = match item {
+            EditorItem::Piece(name) => editor.palette == Some(name),
+            EditorItem::Erase => editor.palette.is_none(),
+            EditorItem::Castling(rook) => editor.board.castling.contains(&rook),
+            _ => false,
+        };
+        let color = if active {
+            theme.selected.0
+        } else {
+            theme.current_move.0
+        };
+        Rectangle::new(color).draw(rect, &c.draw_state, c.transform, g);
+        match item {
+            EditorItem::Piece(name) => {
+                let center = [rect[0] + rect[2] / 2.0, rect[1] + rect[3] / 2.0];
+                draw_piece(c, g, skin, name, layout, center, rect[2]);
+            }
+            _ => {
+                let pos = [rect[0] + 6.0, rect[1] + 17.0];
+                draw_text(
+                    c,
+                    g,
+                    &mut skin.glyphs,
+                    &label,
+                    layout,
+                    16,
+                    theme.text.0,
+                    pos,
+                );
+            }
+        }
+    }
+
+    // the FEN is split after the fourth rank so it fits the panel
+    let fen = editor.board.to_fen();
+    let (ranks, rest) = fen.split_once(' ').unwrap_or((&fen, ""));
+    let split = ranks
+        .match_indices('/')
+        .nth(3)
+        .map_or(ranks.len(), |(i, _)| i + 1);
+    let lines = [&ranks[..split], &ranks[split..], rest];
+    for (i, line) in lines.iter().enumerate() {
+        let y = bottom + 28.0 + i as f64 * 20.0;
+        draw_text(
+            c,
+            g,
+            &mut skin.glyphs,
+            line,
+            layout,
+            16,
+            theme.text.0,
+            [PANEL_X, y],
+        );
+    }
+
+    let status = match editor.board.validate() {
+        Ok(()) => "Legal position".to_string(),
+        Err(e) => e,
+    };
+    let y = BOARD_SIZE - LIST_TOP;
+    draw_text(
+        c,
+        g,
+        &mut skin.glyphs,
+        &status,
+        layout,
+        16,
+        theme.text.0,
+        [PANEL_X, y],
+    );
+}
+
+// The setup panel button under a point
+pub fn editor_item_at(point: [f64; 2], editor: &Editor, view: &View) -> Option<EditorItem> {
+    let point = view.layout.design_point(point);
+    editor_buttons(editor)
+        .into_iter()
+        .find(|(_, rect, _)| {
+            (rect[0]..rect[0] + rect[2]).contains(&point[0])
+                && (rect[1]..rect[1] + rect[3]).contains(&point[1])
+        })
+        .map(|(item, _, _)| item)
+}
+
+// The menu button under a point
+pub fn menu_at(point: [f64; 2], view: &View) -> Option<MenuItem> {
+    let point = view.layout.design_point(point);
+    if point[1] < MENU_Y || point[1] >= MENU_Y + ROW_HEIGHT - 2.0 || point[0] < PANEL_X {
+        return None;
+    }
+    let i = ((point[0] - PANEL_X) / (MENU_WIDTH + 4.0)).floor() as usize;
+    let inside = point[0] - PANEL_X - i as f64 * (MENU_WIDTH + 4.0) < MENU_WIDTH;
+    MENU.get(i).filter(|_| inside).map(|(item, _)| *item)
+}
+
+// Text at a design position, with its size in design units
+#[allow(clippy::too_many_arguments)]
+fn draw_text<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    glyphs: &mut GlyphCache,
+    text: &str,
+    layout: Layout,
+    size: u32,
+    color: [f32; 4],
+    pos: [f64; 2],
+) {
+    // undo the layout scale, the glyphs are already rendered at the window size
+    let transform = c
+        .transform
+        .trans(pos[0], pos[1])
+        .scale(1.0 / layout.scale, 1.0 / layout.scale);
+    graphics::text::Text::new_color(color, layout.font_size(size))
+        .draw(text, glyphs, &c.draw_state, transform, g)
+        .unwrap();
+}
+
+// Linear blend between two colors, `t` is the amount of `b`
+fn mix(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+// Draws a piece centered on a design position, from the theme's piece images
+// if it has them and as a font glyph otherwise. `cell` is the size of the square.
+#[allow(clippy::too_many_arguments)]
+fn draw_piece<G: Graphics<Texture = Texture>>(
+    c: &Context,
+    g: &mut G,
+    skin: &mut Skin,
+    name: char,
+    layout: Layout,
+    center: [f64; 2],
+    cell: f64,
+) {
+    if let Some(sprite) = skin.sprites.get(&name) {
+        let size = cell * 0.9;
+        Image::new()
+            .rect([center[0] - size / 2.0, center[1] - size / 2.0, size, size])
+            .draw(sprite, &c.draw_state, c.transform, g);
+        return;
+    }
+    let glyphs = &mut skin.glyphs;
+    // two thirds of a square, measured so the glyph can be centered at any size
+    let size = layout.font_size((cell * 2.0 / 3.0) as u32);
+    let text = unicode_glyph(name).to_string();
+    let width = glyphs.width(size, &text).unwrap_or(0.0);
+    // A transform to move the placement of the text. The glyphs sit on the
+    // baseline, which is about half the font size below their middle.
+    let piece_transform = c
+        .transform
+        .trans(center[0], center[1])
+        .scale(1.0 / layout.scale, 1.0 / layout.scale)
+        .trans(-width / 2.0, size as f64 * 0.48);
+    graphics::text::Text::new(size)
+        .draw(&text, glyphs, &c.draw_state, piece_transform, g)
+        .unwrap();
+
+    // For some reason this is needed for the text to render correctly
+    // draws a space in another color
+    graphics::text::Text::new_color([1.0; 4], 32)
+        .draw(" ", glyphs, &c.draw_state, c.transform, g)
+        .unwrap();
+}
+
+// The colors Lichess draws arrows and marks in, a little see-through
+fn highlight_color(color: Highlight) -> [f32; 4] {
+    match color {
+        Highlight::Green => [0.08, 0.47, 0.11, 0.8],
+        Highlight::Red => [0.53, 0.13, 0.13, 0.8],
+        Highlight::Yellow => [0.9, 0.56, 0.0, 0.8],
+        Highlight::Blue => [0.0, 0.19, 0.53, 0.8],
+    }
+}
+
+// An arrow from the middle of one square to the middle of another, over the
+// pieces. A drop is just the head on its square.
+fn draw_arrow<G: Graphics>(
+    c: &Context,
+    g: &mut G,
+    board: &Board,
+    view: &View,
+    from: usize,
+    to: usize,
+    color: [f32; 4],
+) {
+    let cell = cell_size(board);
+    let center = |pos: usize| {
+        let on_screen = view_square(board, pos, view.flipped);
+        [
+            ((on_screen % board.width) as f64 + 0.5) * cell,
+            ((on_screen / board.width) as f64 + 0.5) * cell,
+        ]
+    };
+    let head = cell * 0.4;
+    let (start, mut tip) = (center(from), center(to));
+    let (dx, dy) = (tip[0] - start[0], tip[1] - start[1]);
+    let length = (dx * dx + dy * dy).sqrt();
+    // a unit step along the arrow, a drop has no direction and points up
+    let (ux, uy) = if from == to {
+        tip[1] -= head / 2.0;
+        (0.0, -1.0)
+    } else {
+        (dx / length, dy / length)
+    };
+    let base = [tip[0] - ux * head, tip[1] - uy * head];
+    if length > head {
+        Line::new(color, cell * 0.08).draw(
+            [start[0], start[1], base[0], base[1]],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+    }
+    // half the width of the head, across the arrow
+    let (nx, ny) = (-uy * head * 0.6, ux * head * 0.6);
+    Polygon::new(color).draw(
+        &[
+            tip,
+            [base[0] + nx, base[1] + ny],
+            [base[0] - nx, base[1] - ny],
+        ],
+        &c.draw_state,
+        c.transform,
+        g,
+    );
+}
+
+// Squares are as big as fits the longer side of the board into the board area
+fn cell_size(board: &Board) -> f64 {
+    BOARD_SIZE / board.width.max(board.height) as f64
+}
+
+// Maps a board square to the square drawn at that place on screen and back.
+// Flipped boards have black at the bottom, so the order is just reversed.
+pub fn view_square(board: &Board, pos: usize, flipped: bool) -> usize {
+    if flipped {
+        board.size() - 1 - pos
+    } else {
+        pos
+    }
+}
+
+// The square of `board` under a point in the window, if there is one
+pub fn square_at(point: [f64; 2], board: &Board, view: &View) -> Option<usize> {
+    let point = view.layout.design_point(point);
+    let cell = cell_size(board);
+    let x = (point[0] / cell).floor();
+    let y = (point[1] / cell).floor();
+    if !(0.0..board.width as f64).contains(&x) || !(0.0..board.height as f64).contains(&y) {
+        return None;
+    }
+    Some(view_square(
+        board,
+        x as usize + board.width * y as usize,
+        view.flipped,
+    ))
 }
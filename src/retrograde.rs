@@ -0,0 +1,620 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+
+use crate::{Board, GameState, Piece, Variant};
+
+// The endgames that can be solved, white's pieces against the bare black king
+pub const ENDGAMES: [&str; 4] = ["KQK", "KRK", "KPK", "KBNK"];
+
+// The first bytes of a table file
+const MAGIC: &[u8; 4] = b"CDTM";
+
+// What a table keeps for every position: 0 for a draw, ILLEGAL for one that
+// can't happen or is stored under another index, otherwise the plies to mate
+// plus one
+const ILLEGAL: u8 = 255;
+// the move count of a black position that can always get to a draw
+const NEVER: u8 = 255;
+
+const KING: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+const KNIGHT: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+// The result of a position for the side to move with perfect play, in plies
+// until mate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distance {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+impl Distance {
+    // Full moves as players count them, so a win in 5 plies is a mate in 3
+    pub fn moves(self) -> u32 {
+        match self {
+            Distance::Win(plies) => plies.div_ceil(2),
+            Distance::Loss(plies) => plies / 2,
+            Distance::Draw => 0,
+        }
+    }
+}
+
+// The distance to mate of every position of an endgame, found by working
+// back from the mates. Symmetric positions are stored once: the white king
+// is kept on the a to d files, and without pawns in the a8-d8-d5 triangle.
+#[derive(Clone, Debug)]
+pub struct Table {
+    // e.g. "KQK"
+    pub name: String,
+    // the FEN letters of the white king, the black king and white's pieces
+    letters: Vec<char>,
+    // the squares the white king is stored on
+    kings: Vec<usize>,
+    values: Vec<u8>,
+}
+
+impl Table {
+    // A table with every position still a draw
+    fn empty(name: &str) -> Result<Table, String> {
+        let name = name.to_ascii_uppercase();
+        if !ENDGAMES.contains(&name.as_str()) {
+            return Err(format!(
+                "Unknown endgame {}, try one of: {}",
+                name,
+                ENDGAMES.join(", ")
+            ));
+        }
+        let mut letters = vec!['K', 'k'];
+        letters.extend(name[1..name.len() - 1].chars());
+        let pawns = letters.contains(&'P');
+        let kings: Vec<usize> = (0..64)
+            .filter(|&sq| {
+                let (file, row) = (sq % 8, sq / 8);
+                file <= 3 && (pawns || row <= file)
+            })
+            .collect();
+        let len = 2 * kings.len() * 64usize.pow(letters.len() as u32 - 1);
+        Ok(Table {
+            name,
+            letters,
+            kings,
+            values: vec![0; len],
+        })
+    }
+
+    // Solves an endgame, see ENDGAMES. KPK solves KQK and KRK first for what
+    // the pawn promotes to.
+    pub fn solve(name: &str) -> Result<Table, String> {
+        let mut table = Table::empty(name)?;
+        let promotions = table.promotion_tables()?;
+        let len = table.values.len();
+        // for black: how many of its moves don't lose yet
+        let mut counts = vec![0u8; len];
+        // for white: the quickest mate by promoting, stored like the values
+        let mut exits = vec![0u8; len];
+        for index in 0..len {
+            let (squares, white) = table.position(index);
+            let board = match table.board(&squares, white) {
+                Some(board) if table.index(&squares, white) == index => board,
+                _ => {
+                    table.values[index] = ILLEGAL;
+                    continue;
+                }
+            };
+            if white {
+                exits[index] = promotion_exit(&board, &promotions);
+                continue;
+            }
+            match board.game_state {
+                GameState::Checkmate => table.values[index] = 1,
+                GameState::Stalemate => counts[index] = NEVER,
+                _ => {
+                    let mut next = vec![];
+                    for (_, to, _) in board.all_moves() {
+                        // taking a piece leaves a draw
+                        if board.board[to].is_some() {
+                            next.clear();
+                            counts[index] = NEVER;
+                            break;
+                        }
+                        let mut after = squares.clone();
+                        after[1] = to;
+                        next.push(table.index(&after, true));
+                    }
+                    next.sort_unstable();
+                    next.dedup();
+                    if counts[index] != NEVER {
+                        counts[index] = next.len() as u8;
+                    }
+                }
+            }
+        }
+
+        // positions mated in `plies` are known, find the ones a ply further
+        let last_exit = exits.iter().copied().max().unwrap_or(0);
+        let mut plies: u8 = 0;
+        loop {
+            let mut found = false;
+            for (value, &exit) in table.values.iter_mut().zip(&exits) {
+                if exit == plies + 2 && *value == 0 {
+                    *value = plies + 2;
+                    found = true;
+                }
+            }
+            for index in 0..len {
+                if table.values[index] != plies + 1 {
+                    continue;
+                }
+                let (squares, white) = table.position(index);
+                if white {
+                    // black loses once every move it has leads to a white win
+                    let mut previous: Vec<usize> = table
+                        .black_unmoves(&squares)
+                        .iter()
+                        .map(|before| table.index(before, false))
+                        .collect();
+                    previous.sort_unstable();
+                    previous.dedup();
+                    for before in previous {
+                        if table.values[before] == 0 && counts[before] != NEVER {
+                            counts[before] -= 1;
+                            if counts[before] == 0 {
+                                table.values[before] = plies + 2;
+                                found = true;
+                            }
+                        }
+                    }
+                } else {
+                    // white wins with any move to a black loss
+                    for before in table.white_unmoves(&squares) {
+                        let before = table.index(&before, true);
+                        if table.values[before] == 0 {
+                            table.values[before] = plies + 2;
+                            found = true;
+                        }
+                    }
+                }
+            }
+            plies += 1;
+            if !found && plies >= last_exit {
+                break;
+            }
+        }
+        Ok(table)
+    }
+
+    // Checks every position against its moves as the move generator makes
+    // them: a win must be one ply longer than the quickest black loss it can
+    // reach, a loss one ply longer than the slowest white win, and a draw
+    // must have neither. Gives the first position that doesn't add up.
+    pub fn verify(&self) -> Result<(), String> {
+        let promotions = self.promotion_tables()?;
+        for index in 0..self.values.len() {
+            if self.values[index] == ILLEGAL {
+                continue;
+            }
+            let (squares, white) = self.position(index);
+            let Some(board) = self.board(&squares, white) else {
+                continue;
+            };
+            let mut results = vec![];
+            for (from, to, promotion) in board.all_moves() {
+                // only the pieces matter for the probe, so the move is played
+                // without make_move working out the state after it
+                let mut after = board.clone();
+                after.apply_move(from, to, promotion);
+                after.turn = !after.turn;
+                let result = match after.board.iter().flatten().count() {
+                    n if n < self.letters.len() => Some(Distance::Draw),
+                    _ if promotion.is_some() => promotions
+                        .iter()
+                        .find_map(|table| table.probe(&after))
+                        .or(Some(Distance::Draw)),
+                    _ => self.probe(&after),
+                };
+                results.push(result.ok_or_else(|| format!("No entry for {}", after.to_fen()))?);
+            }
+            let expected = match board.game_state {
+                GameState::Checkmate => Distance::Loss(0),
+                _ if white => results
+                    .iter()
+                    .filter_map(|&result| match result {
+                        Distance::Loss(plies) => Some(plies + 1),
+                        _ => None,
+                    })
+                    .min()
+                    .map_or(Distance::Draw, Distance::Win),
+                _ if results.is_empty() => Distance::Draw,
+                _ => results
+                    .iter()
+                    .map(|&result| match result {
+                        Distance::Win(plies) => Some(plies + 1),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<u32>>>()
+                    .and_then(|plies| plies.into_iter().max())
+                    .map_or(Distance::Draw, Distance::Loss),
+            };
+            let stored = self.distance(index);
+            if stored != expected {
+                return Err(format!(
+                    "{}: {} is {:?} in the table but {:?} from its moves",
+                    self.name,
+                    board.to_fen(),
+                    stored,
+                    expected
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The result of a position with the table's material, either color
+    // having the pieces. None for other positions.
+    pub fn probe(&self, board: &Board) -> Option<Distance> {
+        if board.variant != Variant::Standard
+            || board.width != 8
+            || board.height != 8
+            || !board.castling.is_empty()
+        {
+            return None;
+        }
+        // the table has the side with the pieces as white, the other way
+        // round the board is turned over
+        for strong in [true, false] {
+            let mut squares = vec![None; self.letters.len()];
+            let mut fits = true;
+            for (pos, piece) in board.board.iter().enumerate() {
+                let Some(piece) = piece else { continue };
+                let letter = if piece.color() == strong {
+                    piece.name.to_ascii_uppercase()
+                } else {
+                    piece.name.to_ascii_lowercase()
+                };
+                let sq = if strong { pos } else { pos ^ 56 };
+                match (0..self.letters.len())
+                    .find(|&i| self.letters[i] == letter && squares[i].is_none())
+                {
+                    Some(i) => squares[i] = Some(sq),
+                    None => fits = false,
+                }
+            }
+            let squares: Option<Vec<usize>> = squares.into_iter().collect();
+            if let (true, Some(squares)) = (fits, squares) {
+                let index = self.index(&squares, board.turn == strong);
+                return (self.values[index] != ILLEGAL).then(|| self.distance(index));
+            }
+        }
+        None
+    }
+
+    // The longest mate in the table, in moves
+    pub fn longest(&self) -> u32 {
+        let plies = self
+            .values
+            .iter()
+            .filter(|&&value| value != ILLEGAL)
+            .max()
+            .map_or(0, |&value| value.saturating_sub(1));
+        (plies as u32).div_ceil(2)
+    }
+
+    // Saves the table as the endgame name and the values run-length encoded
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.name.len() as u8);
+        bytes.extend(self.name.as_bytes());
+        bytes.extend((self.values.len() as u32).to_le_bytes());
+        for run in self.values.chunk_by(|a, b| a == b) {
+            bytes.push(run[0]);
+            // the run length 7 bits at a time, the top bit says more follow
+            let mut count = run.len();
+            while count >= 0x80 {
+                bytes.push((count & 0x7f) as u8 | 0x80);
+                count >>= 7;
+            }
+            bytes.push(count as u8);
+        }
+        fs::write(path, bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn read(path: &Path) -> Result<Table, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Table::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Table, String> {
+        let invalid = || "Not an endgame table".to_string();
+        if bytes.len() < 5 || &bytes[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let name_end = 5 + bytes[4] as usize;
+        let name = bytes.get(5..name_end).ok_or_else(invalid)?;
+        let mut table = Table::empty(&String::from_utf8_lossy(name))?;
+        let len = bytes.get(name_end..name_end + 4).ok_or_else(invalid)?;
+        if u32::from_le_bytes(len.try_into().unwrap()) as usize != table.values.len() {
+            return Err(invalid());
+        }
+        let mut values = Vec::with_capacity(table.values.len());
+        let mut rest = bytes[name_end + 4..].iter();
+        while let Some(&value) = rest.next() {
+            let mut count = 0;
+            let mut shift = 0;
+            loop {
+                let byte = *rest.next().ok_or_else(invalid)?;
+                count |= ((byte & 0x7f) as usize) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            values.extend(std::iter::repeat_n(value, count));
+        }
+        if values.len() != table.values.len() {
+            return Err(invalid());
+        }
+        table.values = values;
+        Ok(table)
+    }
+
+    fn has_pawn(&self) -> bool {
+        self.letters.contains(&'P')
+    }
+
+    // The tables a pawn can promote into that aren't plain draws
+    fn promotion_tables(&self) -> Result<Vec<Table>, String> {
+        if self.has_pawn() {
+            Ok(vec![Table::solve("KQK")?, Table::solve("KRK")?])
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn distance(&self, index: usize) -> Distance {
+        let (_, white) = self.position(index);
+        match self.values[index] {
+            0 => Distance::Draw,
+            value if white => Distance::Win(value as u32 - 1),
+            value => Distance::Loss(value as u32 - 1),
+        }
+    }
+
+    // Where the position with pieces on `squares`, in the order of the
+    // letters, is stored. Symmetric positions share an index.
+    fn index(&self, squares: &[usize], white: bool) -> usize {
+        let (file, row) = (squares[0] % 8, squares[0] / 8);
+        let mut symmetry = (file > 3) as u8;
+        if !self.has_pawn() {
+            symmetry |= ((row > 3) as u8) << 1;
+            if row.min(7 - row) > file.min(7 - file) {
+                symmetry |= 4;
+            }
+            // a king on the diagonal stays there when the board is flipped
+            // along it, then whichever way puts the other pieces first is used
+            let king = transform(squares[0], symmetry);
+            let rest = |symmetry| squares[1..].iter().map(move |&sq| transform(sq, symmetry));
+            if king % 8 == king / 8 && rest(symmetry ^ 4).lt(rest(symmetry)) {
+                symmetry ^= 4;
+            }
+        }
+        let king = transform(squares[0], symmetry);
+        let mut index = white as usize * self.kings.len()
+            + self.kings.iter().position(|&sq| sq == king).unwrap();
+        for &sq in &squares[1..] {
+            index = index * 64 + transform(sq, symmetry);
+        }
+        index
+    }
+
+    // The squares and side to move of an index, the inverse of index
+    fn position(&self, mut index: usize) -> (Vec<usize>, bool) {
+        let mut squares = vec![0; self.letters.len()];
+        for sq in squares[1..].iter_mut().rev() {
+            *sq = index % 64;
+            index /= 64;
+        }
+        squares[0] = self.kings[index % self.kings.len()];
+        (squares, index >= self.kings.len())
+    }
+
+    // The board of a position, None when two pieces share a square or a pawn
+    // is on the first or last rank. Positions where the side that isn't to
+    // move is in check come out as None too.
+    fn board(&self, squares: &[usize], white: bool) -> Option<Board> {
+        let mut board = vec![None; 64];
+        for (&sq, &letter) in squares.iter().zip(&self.letters) {
+            let pawn_row = letter == 'P' && !(8..56).contains(&sq);
+            if board[sq].is_some() || pawn_row {
+                return None;
+            }
+            board[sq] = Piece::from_char(letter);
+        }
+        let mut board = Board::new(board, 8, white);
+        if board.in_check(!white) {
+            return None;
+        }
+        board.update_state();
+        Some(board)
+    }
+
+    // The positions before white's last move, with white to move
+    fn white_unmoves(&self, squares: &[usize]) -> Vec<Vec<usize>> {
+        let empty = |sq: usize| !squares.contains(&sq);
+        let mut previous = vec![];
+        for i in (0..squares.len()).filter(|&i| i != 1) {
+            let sq = squares[i];
+            let mut from = vec![];
+            match self.letters[i] {
+                // pawns come from below, from their start rank two squares down
+                'P' => {
+                    if sq / 8 < 6 && empty(sq + 8) {
+                        from.push(sq + 8);
+                        if sq / 8 == 4 && empty(sq + 16) {
+                            from.push(sq + 16);
+                        }
+                    }
+                }
+                letter => {
+                    let (steps, range): (&[(i32, i32)], i32) = match letter {
+                        'K' => (&KING, 1),
+                        'N' => (&KNIGHT, 1),
+                        'Q' => (&KING, 7),
+                        'R' => (&KING[..4], 7),
+                        _ => (&KING[4..], 7),
+                    };
+                    for &(df, dr) in steps {
+                        for n in 1..=range {
+                            match offset(sq, df * n, dr * n) {
+                                Some(to) if empty(to) => from.push(to),
+                                _ => break,
+                            }
+                        }
+                    }
+                }
+            }
+            for to in from {
+                let mut before = squares.to_vec();
+                before[i] = to;
+                previous.push(before);
+            }
+        }
+        previous
+    }
+
+    // The positions before black's last king move, with black to move
+    fn black_unmoves(&self, squares: &[usize]) -> Vec<Vec<usize>> {
+        KING.iter()
+            .filter_map(|&(df, dr)| offset(squares[1], df, dr))
+            .filter(|sq| !squares.contains(sq))
+            .map(|sq| {
+                let mut before = squares.to_vec();
+                before[1] = sq;
+                before
+            })
+            .collect()
+    }
+}
+
+// The quickest mate white gets by promoting, stored like a table value
+fn promotion_exit(board: &Board, promotions: &[Table]) -> u8 {
+    if promotions.is_empty() {
+        return 0;
+    }
+    let mut best = 0;
+    for (from, to, promotion) in board.all_moves() {
+        if promotion.is_none() {
+            continue;
+        }
+        let mut after = board.clone();
+        after.make_move(from, to, promotion);
+        // black to move after the promotion, losing in `plies`
+        let plies = promotions
+            .iter()
+            .find_map(|table| match table.probe(&after) {
+                Some(Distance::Loss(plies)) => Some(plies),
+                _ => None,
+            });
+        if let Some(plies) = plies {
+            let value = plies as u8 + 2;
+            if best == 0 || value < best {
+                best = value;
+            }
+        }
+    }
+    best
+}
+
+// A square moved by files and rows, None off the board
+fn offset(sq: usize, files: i32, rows: i32) -> Option<usize> {
+    let (file, row) = ((sq % 8) as i32 + files, (sq / 8) as i32 + rows);
+    ((0..8).contains(&file) && (0..8).contains(&row)).then(|| (row * 8 + file) as usize)
+}
+
+// A square under one of the 8 symmetries of the board: bit 1 mirrors the
+// files, bit 2 the rows and bit 4 then flips along the a8-h1 diagonal
+fn transform(sq: usize, symmetry: u8) -> usize {
+    let (mut file, mut row) = (sq % 8, sq / 8);
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        row = 7 - row;
+    }
+    if symmetry & 4 != 0 {
+        std::mem::swap(&mut file, &mut row);
+    }
+    row * 8 + file
+}
+
+// The tables the engine and the window look positions up in
+static TABLES: LazyLock<RwLock<Vec<Table>>> = LazyLock::new(|| RwLock::new(vec![]));
+
+// Makes a table available to probe, replacing one for the same endgame
+pub fn add_table(table: Table) {
+    let mut tables = TABLES.write().unwrap();
+    tables.retain(|old| old.name != table.name);
+    tables.push(table);
+}
+
+// Adds every .dtm file in a directory. Returns the endgames loaded.
+pub fn load_tables(dir: &Path) -> Result<Vec<String>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut names = vec![];
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_some_and(|ext| ext == "dtm") {
+            let table = Table::read(&path)?;
+            names.push(table.name.clone());
+            add_table(table);
+        }
+    }
+    Ok(names)
+}
+
+// The result of a position from the tables added, None when none has it
+pub fn probe(board: &Board) -> Option<Distance> {
+    if board.board.iter().flatten().count() > 4 {
+        return None;
+    }
+    let tables = TABLES.read().unwrap();
+    tables.iter().find_map(|table| table.probe(board))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn king_and_queen() {
+        let table = Table::solve("KQK").unwrap();
+        assert_eq!(table.longest(), 10);
+        table.verify().unwrap();
+    }
+
+    // slow, run with --ignored
+    #[test]
+    #[ignore]
+    fn king_and_rook() {
+        let table = Table::solve("KRK").unwrap();
+        assert_eq!(table.longest(), 16);
+        table.verify().unwrap();
+    }
+}
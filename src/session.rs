@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::Path;
+
+use chess::{create_board, Game};
+
+// The game in progress is written here after every move and restored on the next start
+pub const AUTOSAVE: &str = "autosave.pgn";
+// where Save writes and Load reads when no `--pgn` file was given
+pub const DEFAULT_PGN: &str = "game.pgn";
+// where the FEN command writes the position on the board
+pub const DEFAULT_FEN: &str = "position.fen";
+
+// Loads a game from a PGN file, or a single position from a .fen file
+pub fn load(path: &Path) -> Result<Game, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let fen = path.extension().is_some_and(|ext| ext == "fen");
+    let game = if fen {
+        create_board(Some(text.trim())).map(Game::new)
+    } else {
+        Game::from_pgn(&text)
+    };
+    game.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn save_pgn(game: &Game, path: &Path) -> Result<(), String> {
+    fs::write(path, game.to_pgn()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Writes the position currently shown, not necessarily the last one
+pub fn save_fen(game: &Game, path: &Path) -> Result<(), String> {
+    let fen = game.board().to_fen() + "\n";
+    fs::write(path, fen).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"4k3/P7/8/8/8/8/8/4K3 w - - 0 1\"]\n\n\
+                   1. a8=R+ $1 {[%cal Ga8e8] Check} (1. a8=Q+) 1... Kd7 *";
+        let game = Game::from_pgn(pgn).unwrap();
+        let path = std::env::temp_dir().join(format!("chess-session-{}.pgn", std::process::id()));
+        save_pgn(&game, &path).unwrap();
+        let loaded = load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().to_pgn(), game.to_pgn());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::crazyhouse::POCKET_ORDER;
+use crate::{Board, Piece, Variant};
+
+// Editing a position piece by piece, for the setup mode of the front ends.
+// The edits keep castling rights and the en passant square consistent with
+// the pieces, everything else is left to validate.
+impl Board {
+    // A board of the given size without any pieces, white to move
+    pub fn empty(width: usize, height: usize) -> Board {
+        Board::new(vec![None; width * height], width, true)
+    }
+
+    // Puts a piece on a square, or clears it with None
+    pub fn place(&mut self, pos: usize, piece: Option<Piece>) {
+        self.board[pos] = piece;
+        self.edited();
+    }
+
+    pub fn set_turn(&mut self, turn: bool) {
+        self.turn = turn;
+        self.edited();
+    }
+
+    // Adds or removes the right to castle with the rook on `rook`.
+    // Returns false when the king and rook aren't where castling needs them.
+    pub fn toggle_castling(&mut self, rook: usize) -> bool {
+        if let Some(i) = self.castling.iter().position(|&r| r == rook) {
+            self.castling.remove(i);
+            return true;
+        }
+        if !self.can_castle_with(rook) {
+            return false;
+        }
+        self.castling.push(rook);
+        true
+    }
+
+    // Puts a piece in the pocket of its color when the variant has drops.
+    // Kings and fairy pieces never go to a pocket.
+    pub fn add_to_pocket(&mut self, name: char) -> bool {
+        if !self.variant.has_drops() || !POCKET_ORDER.contains(name.to_ascii_uppercase()) {
+            return false;
+        }
+        self.pocket.push(name);
+        true
+    }
+
+    // Takes one of a piece out of its pocket, false when there is none
+    pub fn take_from_pocket(&mut self, name: char) -> bool {
+        match self.pocket.iter().position(|&p| p == name) {
+            Some(i) => {
+                self.pocket.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Squares that can be the en passant square: behind a pawn of the side
+    // that just moved, which could have come from a rank it double steps from
+    pub fn en_passant_candidates(&self) -> Vec<usize> {
+        let mover = !self.turn;
+        // ranks from the back rank, the horde's pawns step twice from the first rank too
+        let mut starts: Vec<usize> = self.variant.double_step_rank().into_iter().collect();
+        if self.variant == Variant::Horde && mover {
+            starts.push(0);
+        }
+        let width = self.width;
+        let pawn = if mover { 'P' } else { 'p' };
+        let mut candidates = vec![];
+        for start in starts.into_iter().filter(|start| start + 3 <= self.height) {
+            // the pawn that moved is two ranks in front of its start rank, rank 4
+            // for white and rank 5 for black on a standard board
+            let pawn_row = if mover {
+                self.height - 3 - start
+            } else {
+                start + 2
+            };
+            for pos in pawn_row * width..(pawn_row + 1) * width {
+                // the square it passed and the one it came from
+                let (behind, from) = if mover {
+                    (pos + width, pos + 2 * width)
+                } else {
+                    (pos - width, pos - 2 * width)
+                };
+                if matches!(&self.board[pos], Some(piece) if piece.name == pawn)
+                    && self.board[behind].is_none()
+                    && self.board[from].is_none()
+                {
+                    candidates.push(behind);
+                }
+            }
+        }
+        candidates.sort();
+        candidates
+    }
+
+    // Moves on to the next en passant candidate, or back to none after the last one
+    pub fn cycle_en_passant(&mut self) {
+        let candidates = self.en_passant_candidates();
+        self.en_passant = match self.en_passant {
+            None => candidates.first().copied(),
+            Some(sq) => candidates
+                .iter()
+                .position(|&c| c == sq)
+                .and_then(|i| candidates.get(i + 1).copied()),
+        };
+    }
+
+    // Checks that the position could come up in a game
+    pub fn validate(&self) -> Result<(), String> {
+        for (color, side) in [(true, "White"), (false, "Black")] {
+            let count = |name: char| {
+                let name = if color {
+                    name.to_ascii_uppercase()
+                } else {
+                    name
+                };
+                self.board
+                    .iter()
+                    .flatten()
+                    .filter(|p| p.name == name)
+                    .count()
+            };
+            // antichess kings are ordinary pieces and the horde has none
+            let kings = match self.variant {
+                Variant::Antichess => None,
+                Variant::Horde if color => Some(0),
+                _ => Some(1),
+            };
+            match kings {
+                Some(0) if count('k') > 0 => return Err(format!("{} can't have a king", side)),
+                Some(1) if count('k') != 1 => {
+                    return Err(format!("{} needs exactly one king", side))
+                }
+                _ => {}
+            }
+            // the horde has more pawns and pieces than any army
+            if self.variant == Variant::Horde && color {
+                continue;
+            }
+            if count('p') > self.width {
+                return Err(format!("{} has more than {} pawns", side, self.width));
+            }
+            let pieces = self
+                .board
+                .iter()
+                .flatten()
+                .filter(|p| p.color() == color)
+                .count();
+            if pieces > 2 * self.width {
+                return Err(format!("{} has more than {} pieces", side, 2 * self.width));
+            }
+        }
+        // horde pawns can start on the first rank
+        let first_rank = self.size() - self.width;
+        let first_rank = if self.variant == Variant::Horde {
+            first_rank..first_rank
+        } else {
+            first_rank..self.size()
+        };
+        for pos in (0..self.width).chain(first_rank) {
+            if matches!(&self.board[pos], Some(piece) if piece.name.eq_ignore_ascii_case(&'p')) {
+                return Err(format!("Pawn on {}", self.square_name(pos)));
+            }
+        }
+        if self.in_check(!self.turn) {
+            let side = if self.turn { "Black" } else { "White" };
+            return Err(format!("{} is in check but it's not their move", side));
+        }
+        if let Some(&rook) = self.castling.iter().find(|&&r| !self.can_castle_with(r)) {
+            return Err(format!(
+                "Can't castle with the rook on {}",
+                self.square_name(rook)
+            ));
+        }
+        if let Some(sq) = self.en_passant {
+            if !self.en_passant_candidates().contains(&sq) {
+                return Err(format!(
+                    "{} can't be the en passant square",
+                    self.square_name(sq)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // A rook on its back rank with the king of the same color on it too,
+    // anywhere on the rank so Chess960 positions can be set up
+    pub fn can_castle_with(&self, rook: usize) -> bool {
+        if rook >= self.size() {
+            return false;
+        }
+        let (color, rook_name) = match self.row(rook) {
+            row if row == self.back_row(true) => (true, 'R'),
+            0 => (false, 'r'),
+            _ => return false,
+        };
+        let king = self.king_square(color);
+        matches!(&self.board[rook], Some(piece) if piece.name == rook_name)
+            && king.is_some_and(|king| self.row(king) == self.row(rook))
+    }
+
+    // Drops what the last edit made impossible and updates check and mate
+    fn edited(&mut self) {
+        let castling: Vec<usize> = self
+            .castling
+            .iter()
+            .copied()
+            .filter(|&rook| self.can_castle_with(rook))
+            .collect();
+        self.castling = castling;
+        if let Some(sq) = self.en_passant {
+            if !self.en_passant_candidates().contains(&sq) {
+                self.en_passant = None;
+            }
+        }
+        self.last_move = None;
+        self.selected = None;
+        self.update_state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_board, create_variant_board, Variant};
+
+    #[test]
+    fn en_passant_after_double_step() {
+        let board = create_board(Some(
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2",
+        ))
+        .unwrap();
+        assert_eq!(board.en_passant, board.parse_square("d3"));
+        // nothing came from d2 when d2 is still occupied
+        let fen = "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPPPPPPP/RNBQKBNR b KQkq d3 0 2";
+        assert!(create_board(Some(fen)).is_err());
+    }
+
+    #[test]
+    fn horde_first_rank_double_step() {
+        // the a-pawn went from a1 to a3
+        let fen = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/1PPPPPPP/1PPPPPPP b kq a2 0 1";
+        let board = create_variant_board(Variant::Horde, Some(fen)).unwrap();
+        assert_eq!(board.en_passant, board.parse_square("a2"));
+        assert_eq!(board.to_fen(), fen);
+        // outside the horde no pawn steps from the first rank
+        assert!(create_board(Some(fen)).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use opengl_graphics::{GlyphCache, Texture, TextureSettings};
+use serde::Deserialize;
+
+const DEFAULT_FONT: &str = "fonts/DejaVuSansCondensed-Bold.ttf";
+// directory searched for shared theme files
+const THEME_DIR: &str = "themes";
+
+// An RGBA color, written as "#rrggbb" or "#rrggbbaa" in theme files
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [f32; 4]);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Color, String> {
+        let digits = hex.trim_start_matches('#');
+        if digits.len() != 6 && digits.len() != 8 {
+            return Err(format!(
+                "Invalid color {}: expected #rrggbb or #rrggbbaa",
+                hex
+            ));
+        }
+        let mut color = [1.0; 4];
+        for (i, channel) in color.iter_mut().enumerate().take(digits.len() / 2) {
+            let byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("Invalid color {}", hex))?;
+            *channel = byte as f32 / 255.0;
+        }
+        Ok(Color(color))
+    }
+}
+
+// Colors, font and piece images used by the GUI. Every field is optional in
+// a theme file, missing ones are taken from the classic theme.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub name: String,
+    pub light: Color,
+    pub dark: Color,
+    pub selected: Color,
+    pub last_move: Color,
+    pub check: Color,
+    pub threat: Color,
+    pub move_hint: Color,
+    pub capture_hint: Color,
+    // the hint arrow
+    pub arrow: Color,
+    pub background: Color,
+    pub text: Color,
+    pub current_move: Color,
+    pub font: String,
+    // directory with one image per piece named wP, wN, ... bK, as .png or .svg.
+    // The font glyphs are used when it's not set.
+    pub pieces: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            name: "classic".to_string(),
+            light: Color([0.9, 0.8, 0.75, 1.0]),
+            dark: Color([0.45, 0.4, 0.4, 1.0]),
+            selected: Color([0.3, 0.6, 0.3, 1.0]),
+            last_move: Color([0.85, 0.8, 0.3, 1.0]),
+            check: Color([0.9, 0.1, 0.1, 1.0]),
+            threat: Color([0.9, 0.2, 0.2, 0.25]),
+            move_hint: Color([0.7, 0.7, 0.7, 1.0]),
+            capture_hint: Color([0.6, 0.3, 0.3, 1.0]),
+            arrow: Color([0.2, 0.6, 0.2, 0.7]),
+            background: Color([1.0; 4]),
+            text: Color([0.1, 0.1, 0.1, 1.0]),
+            current_move: Color([0.8, 0.85, 0.95, 1.0]),
+            font: DEFAULT_FONT.to_string(),
+            pieces: None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut theme: Theme =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if theme.name == Theme::default().name {
+            // unnamed themes go by their file name
+            if let Some(stem) = path.file_stem() {
+                theme.name = stem.to_string_lossy().to_string();
+            }
+        }
+        Ok(theme)
+    }
+}
+
+// The themes that come with the game
+pub fn builtin() -> Vec<Theme> {
+    vec![
+        Theme::default(),
+        Theme {
+            name: "green".to_string(),
+            light: Color([0.93, 0.93, 0.82, 1.0]),
+            dark: Color([0.46, 0.59, 0.34, 1.0]),
+            selected: Color([0.73, 0.79, 0.27, 1.0]),
+            ..Theme::default()
+        },
+        Theme {
+            name: "blue".to_string(),
+            light: Color([0.87, 0.89, 0.9, 1.0]),
+            dark: Color([0.55, 0.64, 0.68, 1.0]),
+            selected: Color([0.47, 0.6, 0.8, 1.0]),
+            current_move: Color([0.85, 0.9, 0.8, 1.0]),
+            ..Theme::default()
+        },
+        Theme {
+            name: "wood".to_string(),
+            light: Color([0.94, 0.85, 0.71, 1.0]),
+            dark: Color([0.71, 0.53, 0.39, 1.0]),
+            selected: Color([0.8, 0.7, 0.3, 1.0]),
+            last_move: Color([0.95, 0.9, 0.5, 1.0]),
+            ..Theme::default()
+        },
+        Theme {
+            name: "night".to_string(),
+            light: Color([0.5, 0.5, 0.55, 1.0]),
+            dark: Color([0.3, 0.3, 0.35, 1.0]),
+            selected: Color([0.3, 0.5, 0.7, 1.0]),
+            background: Color([0.15, 0.15, 0.17, 1.0]),
+            text: Color([0.9, 0.9, 0.9, 1.0]),
+            current_move: Color([0.3, 0.35, 0.45, 1.0]),
+            ..Theme::default()
+        },
+    ]
+}
+
+// Built-in themes followed by every .toml file in the themes directory
+pub fn available() -> Vec<Theme> {
+    let mut themes = builtin();
+    if let Ok(entries) = fs::read_dir(THEME_DIR) {
+        let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        paths.sort();
+        for path in paths {
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                match Theme::load(&path) {
+                    Ok(theme) => themes.push(theme),
+                    Err(e) => eprintln!("Skipping theme {}", e),
+                }
+            }
+        }
+    }
+    themes
+}
+
+// A theme with its font and piece images loaded, ready for drawing
+pub struct Skin {
+    pub theme: Theme,
+    pub glyphs: GlyphCache<'static>,
+    pub sprites: HashMap<char, Texture>,
+}
+
+impl Skin {
+    pub fn new(theme: Theme) -> Result<Skin, String> {
+        let glyphs = GlyphCache::new(&theme.font, (), TextureSettings::new())
+            .map_err(|e| format!("Could not load font {}: {}", theme.font, e))?;
+        let mut sprites = HashMap::new();
+        if let Some(dir) = &theme.pieces {
+            for name in "PNBRQKpnbrqk".chars() {
+                let color = if name.is_ascii_uppercase() { 'w' } else { 'b' };
+                let file = format!("{}{}", color, name.to_ascii_uppercase());
+                sprites.insert(name, load_sprite(Path::new(dir), &file)?);
+            }
+        }
+        Ok(Skin {
+            theme,
+            glyphs,
+            sprites,
+        })
+    }
+}
+
+// Size SVG pieces are rasterized at, the texture is scaled down when drawn
+const SPRITE_SIZE: u32 = 256;
+
+fn load_sprite(dir: &Path, file: &str) -> Result<Texture, String> {
+    let settings = TextureSettings::new();
+    let png = dir.join(format!("{}.png", file));
+    if png.exists() {
+        return Texture::from_path(&png, &settings);
+    }
+    let svg = dir.join(format!("{}.svg", file));
+    let data = fs::read(&svg).map_err(|e| format!("{}: {}", svg.display(), e))?;
+    let tree = resvg::usvg::Tree::from_data(&data, &resvg::usvg::Options::default())
+        .map_err(|e| format!("{}: {}", svg.display(), e))?;
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(SPRITE_SIZE, SPRITE_SIZE)
+        .ok_or_else(|| "Could not allocate sprite".to_string())?;
+    let size = tree.size();
+    let scale = SPRITE_SIZE as f32 / size.width().max(size.height());
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    // tiny-skia keeps premultiplied alpha, textures expect it straight
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = image::RgbaImage::from_raw(SPRITE_SIZE, SPRITE_SIZE, pixels)
+        .ok_or_else(|| "Invalid sprite size".to_string())?;
+    Ok(Texture::from_image(&image, &settings))
+}
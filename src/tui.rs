@@ -0,0 +1,362 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use chess::{Board, GameState};
+
+// Same palette as the piston window
+const COLOR1: Color = Color::Rgb {
+    r: 115,
+    g: 102,
+    b: 102,
+};
+const COLOR2: Color = Color::Rgb {
+    r: 230,
+    g: 204,
+    b: 191,
+};
+const SELECTED: Color = Color::Rgb {
+    r: 77,
+    g: 153,
+    b: 77,
+};
+const CURSOR: Color = Color::Rgb {
+    r: 200,
+    g: 170,
+    b: 60,
+};
+const MOVE_DOT: Color = Color::Rgb {
+    r: 179,
+    g: 179,
+    b: 179,
+};
+const CAPTURE_DOT: Color = Color::Rgb {
+    r: 153,
+    g: 77,
+    b: 77,
+};
+
+// Each square is three columns wide so the glyphs have some room
+const SQUARE_WIDTH: u16 = 3;
+const BOARD_LEFT: u16 = 3;
+const BOARD_TOP: u16 = 1;
+
+struct Tui {
+    board: Board,
+    cursor: usize,
+    moves: Vec<String>,
+    // remaining time for white and black
+    clocks: [Duration; 2],
+    last_tick: Instant,
+    flagged: Option<bool>,
+    // a pawn move onto the last rank waiting for the piece to promote to
+    promoting: Option<(usize, usize)>,
+    // set when the whole screen has to be repainted
+    repaint: bool,
+}
+
+impl Tui {
+    fn clock_index(color: bool) -> usize {
+        if color {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.board.is_finished() || self.flagged.is_some()
+    }
+
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        if self.finished() {
+            return;
+        }
+        let clock = &mut self.clocks[Tui::clock_index(self.board.turn)];
+        *clock = clock.saturating_sub(elapsed);
+        if clock.is_zero() {
+            self.flagged = Some(self.board.turn);
+        }
+    }
+
+    // The column the clocks and moves start in, right of the board
+    fn pane_left(&self) -> u16 {
+        BOARD_LEFT + self.board.width as u16 * SQUARE_WIDTH + 4
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        let x = self.board.file(self.cursor) as i32 + dx;
+        let y = self.board.row(self.cursor) as i32 + dy;
+        if let Some(pos) = self.board.square(x, y) {
+            self.cursor = pos;
+        }
+    }
+
+    fn select(&mut self) {
+        if self.finished() {
+            return;
+        }
+        self.repaint = true;
+        let to = self.cursor;
+        match self.board.selected {
+            Some(from) if from != to && self.board.is_legal(from, to, None) => {
+                if self.board.is_promotion(from, to) {
+                    self.promoting = Some((from, to));
+                } else {
+                    self.play(from, to, None);
+                }
+            }
+            _ => {
+                self.board.select_square(to);
+            }
+        }
+    }
+
+    // Finishes a waiting promotion with the piece typed, other keys are ignored
+    fn promote(&mut self, key: char) {
+        let name = key.to_ascii_lowercase();
+        if let Some((from, to)) = self.promoting {
+            if self.board.promotion_pieces().contains(&name) {
+                self.promoting = None;
+                self.play(from, to, Some(name));
+            }
+        }
+    }
+
+    fn play(&mut self, from: usize, to: usize, promotion: Option<char>) {
+        // the notation has to be worked out before the move changes the board
+        let san = self.board.san(from, to, promotion);
+        if self.board.make_move(from, to, promotion) {
+            self.moves.push(san);
+        }
+        self.board.selected = None;
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.repaint {
+            queue!(out, Clear(ClearType::All))?;
+            self.repaint = false;
+        }
+
+        // legal destinations of the selected piece, drawn as dots like the circles in the window
+        let mut targets = vec![];
+        if let Some(sq) = self.board.selected {
+            if self.board.board[sq].is_some() {
+                targets = self.board.get_valid_moves(sq);
+            }
+        }
+
+        let (width, height) = (self.board.width, self.board.height);
+        let pane_left = self.pane_left();
+        for i in 0..self.board.size() {
+            let (file, row) = (self.board.file(i), self.board.row(i));
+            let x = BOARD_LEFT + file as u16 * SQUARE_WIDTH;
+            let y = BOARD_TOP + row as u16;
+            let mut bg = if (file + row) % 2 == 0 {
+                COLOR2
+            } else {
+                COLOR1
+            };
+            if self.board.selected == Some(i) {
+                bg = SELECTED;
+            }
+            if self.cursor == i {
+                bg = CURSOR;
+            }
+            let (fg, text) = match &self.board.board[i] {
+                Some(piece) if targets.contains(&i) => (CAPTURE_DOT, piece.glyph()),
+                Some(piece) => (Color::Black, piece.glyph()),
+                None if targets.contains(&i) => (MOVE_DOT, '\u{2022}'),
+                None => (Color::Black, ' '),
+            };
+            queue!(
+                out,
+                MoveTo(x, y),
+                SetBackgroundColor(bg),
+                SetForegroundColor(fg),
+                Print(format!(" {} ", text)),
+                ResetColor
+            )?;
+        }
+
+        // coordinates
+        for rank in 0..height as u16 {
+            queue!(
+                out,
+                MoveTo(BOARD_LEFT - 2, BOARD_TOP + rank),
+                Print(format!("{:<2}", height as u16 - rank))
+            )?;
+        }
+        for file in 0..width as u16 {
+            queue!(
+                out,
+                MoveTo(
+                    BOARD_LEFT + file * SQUARE_WIDTH + 1,
+                    BOARD_TOP + height as u16
+                ),
+                Print((b'a' + file as u8) as char)
+            )?;
+        }
+
+        // clocks, with a marker for the side to move
+        for (row, (label, color)) in [("White", true), ("Black", false)].iter().enumerate() {
+            let remaining = self.clocks[Tui::clock_index(*color)].as_secs();
+            let marker = if self.board.turn == *color && !self.finished() {
+                '>'
+            } else {
+                ' '
+            };
+            queue!(
+                out,
+                MoveTo(pane_left, BOARD_TOP + row as u16),
+                Print(format!(
+                    "{} {} {:02}:{:02}",
+                    marker,
+                    label,
+                    remaining / 60,
+                    remaining % 60
+                )),
+                Clear(ClearType::UntilNewLine)
+            )?;
+        }
+
+        // move list, scrolled so the latest moves stay visible
+        let (_, lines_left) = terminal::size()?;
+        let list_top = BOARD_TOP + 3;
+        let rows = lines_left.saturating_sub(list_top + 3).max(1) as usize;
+        let lines: Vec<String> = self
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(n, pair)| {
+                format!(
+                    "{:>3}. {:<8}{}",
+                    n + 1,
+                    pair[0],
+                    pair.get(1).map_or("", |m| m)
+                )
+            })
+            .collect();
+        queue!(out, MoveTo(pane_left, list_top), Print("Moves"))?;
+        for (row, line) in lines
+            .iter()
+            .skip(lines.len().saturating_sub(rows))
+            .enumerate()
+        {
+            queue!(
+                out,
+                MoveTo(pane_left, list_top + 1 + row as u16),
+                Print(line)
+            )?;
+        }
+
+        let pieces: Vec<String> = self
+            .board
+            .promotion_pieces()
+            .iter()
+            .map(char::to_string)
+            .collect();
+        let status = match (self.flagged, &self.board.game_state) {
+            (None, _) if self.promoting.is_some() => {
+                format!("Promote to: {}  esc: cancel", pieces.join(" "))
+            }
+            (Some(true), _) => "White ran out of time".to_string(),
+            (Some(false), _) => "Black ran out of time".to_string(),
+            (None, GameState::Checkmate) => format!(
+                "Checkmate, {} wins",
+                if self.board.turn { "Black" } else { "White" }
+            ),
+            (None, GameState::Stalemate) => "Stalemate".to_string(),
+            (None, GameState::GameOver) => "Game over".to_string(),
+            (None, GameState::Won(color)) => format!(
+                "{}, {} wins",
+                self.board.variant,
+                if *color { "White" } else { "Black" }
+            ),
+            (None, GameState::Draw) => format!("{}, draw", self.board.variant),
+            (None, GameState::Check) => "Check!".to_string(),
+            _ => format!(
+                "{} to move",
+                if self.board.turn { "White" } else { "Black" }
+            ),
+        };
+        queue!(
+            out,
+            MoveTo(0, BOARD_TOP + height as u16 + 2),
+            Print(status),
+            Clear(ClearType::UntilNewLine),
+            MoveTo(0, BOARD_TOP + height as u16 + 3),
+            Print("arrows: move  enter/space: select  q: quit")
+        )?;
+        out.flush()
+    }
+}
+
+// Plays a hotseat game in the terminal, `seconds` is the starting time on each clock
+pub fn run(board: Board, seconds: u64) -> io::Result<()> {
+    // start on the e2 pawn, or wherever the second rank's middle is
+    let cursor = board.square(board.width as i32 / 2, board.height as i32 - 2);
+    let mut tui = Tui {
+        board,
+        cursor: cursor.unwrap_or(0),
+        moves: vec![],
+        clocks: [Duration::from_secs(seconds); 2],
+        last_tick: Instant::now(),
+        flagged: None,
+        promoting: None,
+        repaint: true,
+    };
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+
+    let result = event_loop(&mut tui, &mut out);
+
+    execute!(out, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop(tui: &mut Tui, out: &mut impl Write) -> io::Result<()> {
+    loop {
+        tui.tick();
+        tui.draw(out)?;
+        // poll with a timeout so the clocks keep running without input
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
+        match event::read()? {
+            // while promoting the letter keys pick the piece, so q is a queen here
+            Event::Key(key) if key.kind == KeyEventKind::Press && tui.promoting.is_some() => {
+                match key.code {
+                    KeyCode::Char(c) => tui.promote(c),
+                    KeyCode::Esc => {
+                        tui.promoting = None;
+                        tui.repaint = true;
+                    }
+                    _ => {}
+                }
+            }
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Left => tui.move_cursor(-1, 0),
+                KeyCode::Right => tui.move_cursor(1, 0),
+                KeyCode::Up => tui.move_cursor(0, -1),
+                KeyCode::Down => tui.move_cursor(0, 1),
+                KeyCode::Enter | KeyCode::Char(' ') => tui.select(),
+                _ => {}
+            },
+            Event::Resize(_, _) => tui.repaint = true,
+            _ => {}
+        }
+    }
+}
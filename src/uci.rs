@@ -0,0 +1,176 @@
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use chess::engine::{self, Move, MATE};
+use chess::{create_board, Board};
+
+// how deep `go` searches when it gives neither a depth nor any time
+const DEPTH: u32 = 4;
+// as deep as a search with only a time limit goes
+const MAX_DEPTH: u32 = 64;
+// the clock is shared out as if this many moves were left, without movestogo
+const MOVES_TO_GO: u64 = 30;
+
+// Talks UCI on stdin and stdout so chess GUIs can play against the engine.
+// `go` searches deeper one move at a time until its depth or its time is
+// used up, there is no `stop` while searching. With the UCI_Chess960 option
+// castling is written as the king taking its own rook.
+pub fn run() -> io::Result<()> {
+    let start = || create_board(None).expect("The start position is valid");
+    let mut board = start();
+    let mut chess960 = false;
+    let mut out = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                writeln!(out, "id name chess")?;
+                writeln!(out, "option name UCI_Chess960 type check default false")?;
+                writeln!(out, "uciok")?;
+            }
+            Some("isready") => writeln!(out, "readyok")?,
+            // setoption name UCI_Chess960 value true
+            Some("setoption") => {
+                let name = words.iter().position(|&w| w == "name");
+                let value = words.iter().position(|&w| w == "value");
+                if let (Some(name), Some(value)) = (name, value) {
+                    if words[name + 1..value].join(" ") == "UCI_Chess960" {
+                        chess960 = words.get(value + 1) == Some(&"true");
+                    }
+                }
+            }
+            Some("ucinewgame") => board = start(),
+            Some("position") => match position(&words) {
+                Ok(next) => board = next,
+                Err(e) => writeln!(out, "info string {}", e)?,
+            },
+            Some("go") => {
+                let start = Instant::now();
+                let result = match limits(&words, board.turn) {
+                    (depth, Some(time)) => engine::search_timed(&board, depth, time),
+                    (depth, None) => {
+                        engine::search(&board, depth).map(|(mv, score)| (mv, score, depth))
+                    }
+                };
+                match result {
+                    Some((mv, score, depth)) => {
+                        let text = move_text(&board, mv, chess960);
+                        writeln!(
+                            out,
+                            "info depth {} score {} time {} pv {}",
+                            depth,
+                            score_text(score),
+                            start.elapsed().as_millis(),
+                            text
+                        )?;
+                        writeln!(out, "bestmove {}", text)?;
+                    }
+                    None => writeln!(out, "bestmove 0000")?,
+                }
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// How deep and how long "go" searches: `depth`, `movetime`, or a share of
+// the side to move's clock from wtime/btime, winc/binc and movestogo. Times
+// are in milliseconds. Without any of them it's DEPTH moves deep.
+fn limits(words: &[&str], turn: bool) -> (u32, Option<Duration>) {
+    let number = |name: &str| -> Option<u64> {
+        let i = words.iter().position(|&w| w == name)?;
+        words.get(i + 1)?.parse().ok()
+    };
+    let (clock, increment) = if turn {
+        ("wtime", "winc")
+    } else {
+        ("btime", "binc")
+    };
+    let share = number(clock).map(|left| {
+        let moves = number("movestogo").unwrap_or(MOVES_TO_GO).max(1);
+        // never more than half of what's left, so there's time for the next moves
+        (left / moves + number(increment).unwrap_or(0) / 2).min(left / 2)
+    });
+    let time = number("movetime").or(share).map(Duration::from_millis);
+    let depth = number("depth").map(|depth| depth as u32);
+    match (depth, time) {
+        (Some(depth), time) => (depth, time),
+        (None, Some(time)) => (MAX_DEPTH, Some(time)),
+        (None, None) => (DEPTH, None),
+    }
+}
+
+// The board of "position startpos moves e2e4 e7e5" or
+// "position fen <fen> moves ..."
+fn position(words: &[&str]) -> Result<Board, String> {
+    let moves = words
+        .iter()
+        .position(|&w| w == "moves")
+        .unwrap_or(words.len());
+    let mut board = match words.get(1).copied() {
+        Some("startpos") => create_board(None)?,
+        Some("fen") => create_board(Some(&words[2..moves].join(" ")))?,
+        _ => return Err("position needs startpos or fen".to_string()),
+    };
+    for uci in words.iter().skip(moves + 1) {
+        let (from, to, promotion) = board.parse_uci(uci)?;
+        if !board.make_move(from, to, promotion) {
+            return Err(format!("Illegal move {}", uci));
+        }
+    }
+    Ok(board)
+}
+
+// A move in UCI coordinates, castling as the king taking its rook for
+// UCI_Chess960
+fn move_text(board: &Board, (from, to, promotion): Move, chess960: bool) -> String {
+    match board.castling_rook(from, to) {
+        Some(rook) if chess960 => format!("{}{}", board.square_name(from), board.square_name(rook)),
+        _ => board.uci(from, to, promotion),
+    }
+}
+
+// "cp 35", or "mate 3" and "mate -2" in moves
+fn score_text(score: i32) -> String {
+    if score.abs() > MATE / 2 {
+        let moves = (MATE - score.abs() + 1) / 2;
+        format!("mate {}", if score < 0 { -moves } else { moves })
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go(line: &str, turn: bool) -> (u32, Option<Duration>) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        limits(&words, turn)
+    }
+
+    #[test]
+    fn go_limits() {
+        assert_eq!(go("go", true), (DEPTH, None));
+        assert_eq!(go("go depth 6", true), (6, None));
+        let second = Some(Duration::from_millis(1000));
+        assert_eq!(go("go movetime 1000", true), (MAX_DEPTH, second));
+        assert_eq!(go("go depth 3 movetime 1000", false), (3, second));
+        // black's clock with a 2s increment: 60000 / 30 + 2000 / 2
+        let clock = "go wtime 1000 btime 60000 winc 0 binc 2000";
+        assert_eq!(
+            go(clock, false),
+            (MAX_DEPTH, Some(Duration::from_millis(3000)))
+        );
+        // one move to go still keeps half the clock back
+        let last = "go wtime 10000 btime 10000 movestogo 1";
+        assert_eq!(
+            go(last, true),
+            (MAX_DEPTH, Some(Duration::from_millis(5000)))
+        );
+    }
+}
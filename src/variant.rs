@@ -0,0 +1,384 @@
+use crate::{Board, GameState};
+
+// The rules a board is played by. Board asks its variant about everything
+// that differs from standard chess: the start position, which moves are
+// legal, how the game ends and what goes into the FEN.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // a king reaching one of the four center squares wins
+    KingOfTheHill,
+    // the third check wins, FEN has a counter like "3+3" for the checks left
+    ThreeCheck,
+    // captures explode everything but pawns around the target square
+    Atomic,
+    // captures are forced and losing every piece wins, kings are ordinary pieces
+    Antichess,
+    // white has 36 pawns and no king, and wins by mating black
+    Horde,
+    // no checks allowed, the first king to reach the last rank wins
+    RacingKings,
+    // captured pieces can be dropped back on the board, FEN has the pockets like [Qp]
+    Crazyhouse,
+    // one of the two boards of a chess::Bughouse match, drops like crazyhouse but
+    // captured pieces go to the partner on the other board
+    Bughouse,
+    // 10x8 with an archbishop (A) and a chancellor (C) next to the king and queen
+    Capablanca,
+    // 10x10 with the rooks in the corners behind the other pieces, no castling
+    // and pawns starting on the third rank
+    Grand,
+    // 6x6 without bishops, castling or pawn double steps
+    LosAlamos,
+}
+
+pub const VARIANTS: [Variant; 12] = [
+    Variant::Standard,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Atomic,
+    Variant::Antichess,
+    Variant::Horde,
+    Variant::RacingKings,
+    Variant::Crazyhouse,
+    Variant::Bughouse,
+    Variant::Capablanca,
+    Variant::Grand,
+    Variant::LosAlamos,
+];
+
+impl Variant {
+    // The name as written in the PGN Variant tag
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Atomic => "Atomic",
+            Variant::Antichess => "Antichess",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Bughouse => "Bughouse",
+            Variant::Capablanca => "Capablanca",
+            Variant::Grand => "Grand",
+            Variant::LosAlamos => "Los Alamos",
+        }
+    }
+
+    // Reads a variant name, ignoring case, spaces and dashes, so "King of the
+    // Hill", "kingofthehill" and "koth" all work. Chess960 is standard chess
+    // from another start position, so it counts as Standard.
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let variant = match name.as_str() {
+            "standard" | "chess" | "chess960" | "fromposition" => Variant::Standard,
+            "kingofthehill" | "koth" => Variant::KingOfTheHill,
+            "threecheck" | "3check" => Variant::ThreeCheck,
+            "atomic" => Variant::Atomic,
+            "antichess" | "suicide" => Variant::Antichess,
+            "horde" => Variant::Horde,
+            "racingkings" => Variant::RacingKings,
+            "crazyhouse" | "zh" => Variant::Crazyhouse,
+            "bughouse" => Variant::Bughouse,
+            "capablanca" => Variant::Capablanca,
+            "grand" | "grandchess" => Variant::Grand,
+            "losalamos" => Variant::LosAlamos,
+            _ => return None,
+        };
+        Some(variant)
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Horde => {
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+            }
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            Variant::Crazyhouse | Variant::Bughouse => {
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1"
+            }
+            Variant::Capablanca => {
+                "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1"
+            }
+            Variant::Grand => {
+                "r8r/1nbqkcabn1/pppppppppp/10/10/10/10/PPPPPPPPPP/1NBQKCABN1/R8R w - - 0 1"
+            }
+            Variant::LosAlamos => "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1",
+            _ => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+
+    // Whether pieces can be dropped from a pocket
+    pub fn has_drops(self) -> bool {
+        matches!(self, Variant::Crazyhouse | Variant::Bughouse)
+    }
+
+    // The rank pawns can move two squares from, counted from the own back
+    // rank as 0. None when they never can.
+    pub fn double_step_rank(self) -> Option<usize> {
+        match self {
+            Variant::Grand => Some(2),
+            Variant::LosAlamos => None,
+            _ => Some(1),
+        }
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Reads the three-check counter of a FEN, either the checks left as in
+// "3+3" or the checks given as in "+0+0", into the checks given by white and black
+pub(crate) fn parse_check_counter(field: &str) -> Result<[u32; 2], String> {
+    let bad = || format!("Invalid FEN-string: Bad check counter {}", field);
+    let given = field.starts_with('+');
+    let numbers: Vec<u32> = field
+        .trim_start_matches('+')
+        .split('+')
+        .map(|n| n.parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    match numbers[..] {
+        [white, black] if given => Ok([white, black]),
+        [white, black] if white <= 3 && black <= 3 => Ok([3 - white, 3 - black]),
+        _ => Err(bad()),
+    }
+}
+
+impl Board {
+    // Whether the position after a move by `color` is one the variant allows.
+    // Standard chess only forbids leaving the own king in check.
+    pub(crate) fn allowed_after_move(&self, color: bool) -> bool {
+        match self.variant {
+            Variant::Antichess => true,
+            // blowing up the other king wins even when the own king is in check
+            Variant::Atomic => match (self.king_square(color), self.king_square(!color)) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                _ => !self.in_check(color),
+            },
+            Variant::RacingKings => !self.in_check(color) && !self.in_check(!color),
+            _ => !self.in_check(color),
+        }
+    }
+
+    // Atomic kings standing next to each other can't be checked, since
+    // capturing one would blow up the other
+    pub(crate) fn kings_touch(&self) -> bool {
+        match (self.king_square(true), self.king_square(false)) {
+            (Some(white), Some(black)) => {
+                self.file(white).abs_diff(self.file(black)) <= 1
+                    && self.row(white).abs_diff(self.row(black)) <= 1
+            }
+            _ => false,
+        }
+    }
+
+    // Removes the pieces an atomic capture on `pos` blows up: the capturing
+    // piece and every piece but pawns next to it
+    pub(crate) fn explode(&mut self, pos: usize) {
+        self.board[pos] = None;
+        let (file, row) = (self.file(pos), self.row(pos));
+        for sq in 0..self.size() {
+            let near = self.file(sq).abs_diff(file) <= 1 && self.row(sq).abs_diff(row) <= 1;
+            let pawn = matches!(&self.board[sq], Some(p) if p.name.eq_ignore_ascii_case(&'p'));
+            if near && !pawn {
+                self.board[sq] = None;
+            }
+        }
+        // rooks and kings that are gone can't castle any more
+        let castling: Vec<usize> = self
+            .castling
+            .iter()
+            .copied()
+            .filter(|&rook| {
+                let color = self.row(rook) == self.back_row(true);
+                self.board[rook].is_some() && self.king_square(color).is_some()
+            })
+            .collect();
+        self.castling = castling;
+    }
+
+    // The pieces a pawn can promote to, antichess allows kings too
+    pub fn promotion_pieces(&self) -> &'static [char] {
+        match self.variant {
+            Variant::Antichess => &['q', 'r', 'b', 'n', 'k'],
+            Variant::Capablanca | Variant::Grand => &['q', 'c', 'a', 'r', 'b', 'n'],
+            Variant::LosAlamos => &['q', 'r', 'n'],
+            _ => &['q', 'r', 'b', 'n'],
+        }
+    }
+
+    // The middle four squares, d4, e4, d5 and e5 on a standard board
+    fn is_center(&self, pos: usize) -> bool {
+        let middle = |n: usize, size: usize| n == size / 2 || n + 1 == size / 2;
+        middle(self.file(pos), self.width) && middle(self.row(pos), self.height)
+    }
+
+    // Whether a pseudo move takes something, en passant included
+    pub(crate) fn is_capture(&self, from: usize, to: usize) -> bool {
+        let pawn = matches!(&self.board[from], Some(p) if p.name.eq_ignore_ascii_case(&'p'));
+        self.board[to].is_some()
+            || (pawn && self.file(from) != self.file(to) && self.en_passant == Some(to))
+    }
+
+    // Antichess forces a capture whenever the side to move has one
+    pub(crate) fn must_capture(&self) -> bool {
+        (0..self.size()).any(|from| match &self.board[from] {
+            Some(piece) if piece.color == self.turn => self
+                .pseudo_moves(from)
+                .into_iter()
+                .any(|to| self.is_capture(from, to)),
+            _ => false,
+        })
+    }
+
+    // How the variant ends the game in this position, if it does. `self.turn`
+    // is the side to move, so the side that just moved is !self.turn.
+    pub(crate) fn variant_end(&self) -> Option<GameState> {
+        let mover = !self.turn;
+        match self.variant {
+            Variant::Standard
+            | Variant::Crazyhouse
+            | Variant::Bughouse
+            | Variant::Capablanca
+            | Variant::Grand
+            | Variant::LosAlamos => None,
+            Variant::KingOfTheHill => self
+                .king_square(mover)
+                .filter(|&king| self.is_center(king))
+                .map(|_| GameState::Won(mover)),
+            Variant::ThreeCheck => {
+                let given = self.checks[if mover { 0 } else { 1 }];
+                (given >= 3).then_some(GameState::Won(mover))
+            }
+            Variant::Atomic => self
+                .king_square(self.turn)
+                .is_none()
+                .then_some(GameState::Won(mover)),
+            // running out of pieces or moves wins
+            Variant::Antichess => self
+                .legal_moves()
+                .is_empty()
+                .then_some(GameState::Won(self.turn)),
+            Variant::Horde => {
+                let white_left = self.board.iter().flatten().any(|p| p.color);
+                (!white_left).then_some(GameState::Won(false))
+            }
+            Variant::RacingKings => {
+                let home = |color: bool| {
+                    self.king_square(color)
+                        .is_some_and(|king| self.row(king) == 0)
+                };
+                match (home(true), home(false)) {
+                    (true, true) => Some(GameState::Draw),
+                    (false, true) => Some(GameState::Won(false)),
+                    // black gets one more move to draw by reaching the last rank too
+                    (true, false) if !self.turn => {
+                        let catch_up = self.king_square(false).is_some_and(|king| {
+                            self.get_valid_moves(king)
+                                .iter()
+                                .any(|&to| self.row(to) == 0)
+                        });
+                        (!catch_up).then_some(GameState::Won(true))
+                    }
+                    (true, false) => Some(GameState::Won(true)),
+                    (false, false) => None,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_variant_board;
+    use crate::tests::perft;
+
+    fn board(variant: Variant, fen: Option<&str>) -> Board {
+        create_variant_board(variant, fen).unwrap()
+    }
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for san in moves {
+            let (from, to, promotion) = board.parse_san(san).unwrap();
+            assert!(board.make_move(from, to, promotion), "{}", san);
+        }
+    }
+
+    #[test]
+    fn king_of_the_hill_center_wins() {
+        let mut board = board(
+            Variant::KingOfTheHill,
+            Some("4k3/8/8/8/8/3K4/8/8 w - - 0 1"),
+        );
+        play(&mut board, &["Kd4"]);
+        assert_eq!(board.game_state, GameState::Won(true));
+    }
+
+    #[test]
+    fn three_check_third_check_wins() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1";
+        let mut board = board(Variant::ThreeCheck, Some(fen));
+        play(&mut board, &["Ra8+", "Kd7", "Ra7+"]);
+        assert_eq!(board.game_state, GameState::Won(true));
+    }
+
+    #[test]
+    fn atomic_exploding_the_king_wins() {
+        let mut board = board(Variant::Atomic, None);
+        assert_eq!(perft(&board, 3), 8902);
+        play(&mut board, &["e3", "a6", "Qh5", "a5", "Qxf7"]);
+        assert_eq!(board.game_state, GameState::Won(true));
+    }
+
+    #[test]
+    fn perft_antichess() {
+        assert_eq!(perft(&board(Variant::Antichess, None), 3), 8067);
+    }
+
+    #[test]
+    fn perft_horde() {
+        assert_eq!(perft(&board(Variant::Horde, None), 3), 1274);
+    }
+
+    #[test]
+    fn perft_racing_kings() {
+        assert_eq!(perft(&board(Variant::RacingKings, None), 3), 11264);
+    }
+
+    // five king moves, four pieces on 62 squares and pawns on the 48 off the
+    // first and last ranks
+    #[test]
+    fn perft_drops() {
+        let fen = "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1";
+        assert_eq!(perft(&board(Variant::Crazyhouse, Some(fen)), 1), 301);
+        assert_eq!(perft(&board(Variant::Bughouse, Some(fen)), 1), 301);
+    }
+
+    #[test]
+    fn perft_capablanca() {
+        assert_eq!(perft(&board(Variant::Capablanca, None), 3), 25228);
+    }
+
+    #[test]
+    fn perft_grand() {
+        assert_eq!(perft(&board(Variant::Grand, None), 2), 4225);
+    }
+
+    #[test]
+    fn perft_los_alamos() {
+        assert_eq!(perft(&board(Variant::LosAlamos, None), 3), 1212);
+    }
+}